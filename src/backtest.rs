@@ -0,0 +1,636 @@
+use crate::error::{read_rows, LoadError};
+use crate::rebalance::{
+    band_rebalance, drift_metrics, format_f64, format_rational, full_rebalance, lazy_rebalance,
+    parse_decimal, portfolio_total, to_f64, Asset, BandTarget, RebalancePlan,
+};
+use crate::schedule::Frequency;
+use chrono::{Months, NaiveDate};
+use num::{BigInt, BigRational, Signed, Zero};
+use serde::Serialize;
+use std::{collections::HashMap, fmt, io::Write, path::Path, str::FromStr};
+use tabwriter::TabWriter;
+
+/// The price of one asset over time, oldest first
+pub type PriceSeries = Vec<(NaiveDate, BigRational)>;
+
+/// How a backtest keeps the portfolio on target
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Strategy {
+    /// every contribution is lazy rebalanced, nothing is ever sold
+    Lazy,
+    /// contributions are lazy rebalanced and the whole portfolio is fully rebalanced every
+    /// few months
+    Calendar,
+    /// contributions are band rebalanced, and so is the portfolio on any day an asset drifts
+    /// out of its band
+    Band,
+}
+
+impl Strategy {
+    pub const ALL: [Strategy; 3] = [Strategy::Lazy, Strategy::Calendar, Strategy::Band];
+}
+
+impl fmt::Display for Strategy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Strategy::Lazy => write!(f, "lazy"),
+            Strategy::Calendar => write!(f, "calendar"),
+            Strategy::Band => write!(f, "band"),
+        }
+    }
+}
+
+/// A fixed contribution replayed over the price history, e.g. $500 monthly with a calendar
+/// rebalance every 12 months
+#[derive(Clone)]
+pub struct Backtest {
+    /// cash contributed every period
+    pub amount: BigRational,
+    pub frequency: Frequency,
+    /// months between the full rebalances of the calendar strategy
+    pub calendar_months: u32,
+}
+
+impl fmt::Display for Backtest {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "${} {}, calendar rebalance every {} months",
+            format_rational(&self.amount, 2),
+            self.frequency,
+            self.calendar_months
+        )
+    }
+}
+
+/// Parses the backtest typed into the TUI: the amount, the frequency and the months between
+/// calendar rebalances separated by commas, e.g. `500, monthly, 12`
+impl FromStr for Backtest {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let columns: Vec<&str> = s.split(',').map(str::trim).collect();
+        let (amount, frequency, months) = match columns[..] {
+            [amount, frequency, months] => (amount, frequency, months),
+            _ => {
+                return Err(
+                    "Enter the amount, frequency and calendar months separated by commas"
+                        .to_string(),
+                )
+            }
+        };
+
+        let amount = parse_decimal(amount)
+            .filter(|amount| *amount >= BigRational::zero())
+            .ok_or_else(|| format!("{} is not a dollar amount", amount))?;
+        let frequency = frequency.parse()?;
+        let calendar_months = match months.parse::<u32>() {
+            Ok(months) if months > 0 => months,
+            _ => return Err(format!("{} is not a number of months", months)),
+        };
+
+        Ok(Backtest {
+            amount,
+            frequency,
+            calendar_months,
+        })
+    }
+}
+
+/// How one strategy did over the price history
+pub struct BacktestResult {
+    pub strategy: Strategy,
+    pub final_value: BigRational,
+    /// cash contributed over the backtest, the starting portfolio left out
+    pub contributed: BigRational,
+    /// dollars sold to rebalance, lazy rebalancing never sells
+    pub sold: BigRational,
+    /// half the dollars bought and sold over the average portfolio value, contributions
+    /// included. A full swap of one asset for another counts once
+    pub turnover: BigRational,
+    /// number of buys and sells
+    pub trades: usize,
+    /// the largest total drift reached on any price date, as a fraction of the portfolio
+    pub max_drift: BigRational,
+    /// time weighted annual growth, so contributions don't count as returns
+    pub cagr: f64,
+    /// the portfolio value on every price date once that date's trades are made
+    pub values: Vec<(NaiveDate, f64)>,
+}
+
+/// Reads the prices file, each row is an asset and the CSV file holding its prices, e.g.
+/// `Domestic Stock ETF, prices/domestic.csv`. Paths are relative to the prices file. Every row
+/// of a price file is a date and a price, e.g. `2024-01-31, 217.50`, daily or monthly
+pub fn read_prices(path: &str) -> Result<HashMap<String, PriceSeries>, Vec<LoadError>> {
+    let mut errors = vec![];
+    let rows = read_rows(path, &mut errors);
+    let dir = Path::new(path).parent().unwrap_or_else(|| Path::new(""));
+
+    let mut prices = HashMap::new();
+    for row in rows {
+        let (name, file) = match (row.text(0), row.text(1)) {
+            (Ok(name), Ok(file)) => (name, file),
+            (Err(error), _) | (_, Err(error)) => {
+                errors.push(error);
+                continue;
+            }
+        };
+
+        let series_path = dir.join(file).to_string_lossy().to_string();
+        let mut series = vec![];
+        for row in read_rows(&series_path, &mut errors) {
+            match (row.date(0), row.decimal(1)) {
+                (Ok(date), Ok(price)) => series.push((date, price)),
+                (Err(error), _) | (_, Err(error)) => errors.push(error),
+            }
+        }
+        series.sort_by_key(|(date, _)| *date);
+
+        prices.insert(name.to_string(), series);
+    }
+
+    if errors.is_empty() {
+        Ok(prices)
+    } else {
+        Err(errors)
+    }
+}
+
+/// Replays `backtest` with every strategy over the dates all the assets have prices for. The
+/// portfolio starts out as `assets` bought at the first prices, the first contribution is made
+/// on the first date and prices are carried forward over the dates an asset has none. The band
+/// strategy gives assets without a band the 5/25 rule. Fractional shares are held, to six
+/// places, and trades are rounded to the cent
+pub fn run_backtest(
+    assets: &[Asset],
+    prices: &HashMap<String, PriceSeries>,
+    backtest: &Backtest,
+    band_target: BandTarget,
+) -> Result<Vec<BacktestResult>, String> {
+    if assets.is_empty() {
+        return Err("The portfolio has no assets to backtest".to_string());
+    }
+
+    let missing: Vec<&str> = assets
+        .iter()
+        .filter(|asset| prices.get(&asset.name).map_or(true, Vec::is_empty))
+        .map(|asset| asset.name.as_str())
+        .collect();
+    if !missing.is_empty() {
+        return Err(format!("No prices for {}", missing.join(", ")));
+    }
+
+    let series: Vec<&PriceSeries> = assets.iter().map(|asset| &prices[&asset.name]).collect();
+    for (asset, series) in assets.iter().zip(&series) {
+        if let Some((date, price)) = series
+            .iter()
+            .find(|(_, price)| *price <= BigRational::zero())
+        {
+            return Err(format!(
+                "{} has a price of {} on {}, prices must be above zero",
+                asset.name,
+                format_rational(price, 2),
+                date
+            ));
+        }
+    }
+
+    let start = series.iter().map(|series| series[0].0).max().unwrap();
+    let end = series
+        .iter()
+        .map(|series| series[series.len() - 1].0)
+        .min()
+        .unwrap();
+    if start >= end {
+        return Err("The price files have no dates in common".to_string());
+    }
+
+    let mut dates: Vec<NaiveDate> = series
+        .iter()
+        .flat_map(|series| series.iter().map(|(date, _)| *date))
+        .filter(|date| *date >= start && *date <= end)
+        .collect();
+    dates.sort();
+    dates.dedup();
+
+    // the price of every asset on every date, the last known price carried forward
+    let mut positions = vec![0; series.len()];
+    let price_rows: Vec<Vec<BigRational>> = dates
+        .iter()
+        .map(|date| {
+            series
+                .iter()
+                .zip(positions.iter_mut())
+                .map(|(series, position)| {
+                    while *position + 1 < series.len() && series[*position + 1].0 <= *date {
+                        *position += 1;
+                    }
+                    series[*position].1.clone()
+                })
+                .collect()
+        })
+        .collect();
+
+    Ok(Strategy::ALL
+        .iter()
+        .map(|strategy| {
+            replay(
+                *strategy,
+                assets,
+                &dates,
+                &price_rows,
+                backtest,
+                band_target,
+            )
+        })
+        .collect())
+}
+
+fn replay(
+    strategy: Strategy,
+    assets: &[Asset],
+    dates: &[NaiveDate],
+    price_rows: &[Vec<BigRational>],
+    backtest: &Backtest,
+    band_target: BandTarget,
+) -> BacktestResult {
+    let hundred = BigRational::from_integer(BigInt::from(100));
+    let start = dates[0];
+
+    let mut portfolio: Vec<Asset> = assets
+        .iter()
+        .map(|asset| {
+            let mut asset = asset.clone();
+            // the shares are tracked below, whole share rounding doesn't apply
+            asset.shares = None;
+            asset.price = None;
+            if strategy == Strategy::Band && asset.tolerance().is_none() {
+                asset.set_bands(
+                    Some(BigRational::from_integer(BigInt::from(5)) / &hundred),
+                    Some(BigRational::from_integer(BigInt::from(25)) / &hundred),
+                );
+            }
+            asset
+        })
+        .collect();
+    let mut shares: Vec<BigRational> = portfolio
+        .iter()
+        .zip(&price_rows[0])
+        .map(|(asset, price)| round_to(&(&asset.value / price), 6))
+        .collect();
+
+    let mut result = BacktestResult {
+        strategy,
+        final_value: BigRational::zero(),
+        contributed: BigRational::zero(),
+        sold: BigRational::zero(),
+        turnover: BigRational::zero(),
+        trades: 0,
+        max_drift: BigRational::zero(),
+        cagr: 0.0,
+        values: vec![],
+    };
+    let mut growth = 1.0;
+    let mut previous_total: Option<BigRational> = None;
+    let mut contributions = 0;
+    let mut calendar_rebalances = 1;
+    let mut traded = BigRational::zero();
+    let mut value_sum = BigRational::zero();
+
+    for (date, prices) in dates.iter().zip(price_rows) {
+        for ((asset, shares), price) in portfolio.iter_mut().zip(&shares).zip(prices) {
+            asset.value = shares * price;
+        }
+        let total = portfolio_total(&portfolio);
+
+        if let Some(previous) = previous_total {
+            if previous > BigRational::zero() {
+                growth *= to_f64(&(&total / previous));
+            }
+        }
+        let drift = drift_metrics(&portfolio).total_drift;
+        if drift > result.max_drift {
+            result.max_drift = drift;
+        }
+
+        // catch up on every contribution since the last price date
+        let mut contribution = BigRational::zero();
+        let mut contribution_due = false;
+        while backtest.frequency.advance(start, contributions) <= *date {
+            contribution += &backtest.amount;
+            contribution_due = true;
+            contributions += 1;
+        }
+
+        let mut calendar_due = false;
+        while start + Months::new(backtest.calendar_months * calendar_rebalances) <= *date {
+            calendar_due = true;
+            calendar_rebalances += 1;
+        }
+
+        let plan: Option<RebalancePlan> = if &total + &contribution <= BigRational::zero() {
+            None
+        } else {
+            match strategy {
+                Strategy::Calendar if calendar_due => {
+                    Some(full_rebalance(&contribution, &portfolio))
+                }
+                Strategy::Lazy | Strategy::Calendar if contribution_due => {
+                    Some(lazy_rebalance(&contribution, &portfolio))
+                }
+                Strategy::Band
+                    if contribution_due
+                        || portfolio.iter().any(|asset| asset.is_out_of_band(&total)) =>
+                {
+                    Some(band_rebalance(&contribution, band_target, &portfolio))
+                }
+                _ => None,
+            }
+        };
+
+        if let Some(plan) = plan {
+            for ((shares, trade), price) in shares.iter_mut().zip(&plan.assets).zip(prices) {
+                let delta = round_to(&trade.delta, 2);
+                if delta.is_zero() {
+                    continue;
+                }
+
+                result.trades += 1;
+                traded += delta.abs();
+                if delta.is_negative() {
+                    result.sold -= &delta;
+                }
+                *shares = round_to(&(&*shares + delta / price), 6);
+            }
+            result.contributed += contribution;
+        }
+
+        let total_after = shares
+            .iter()
+            .zip(prices)
+            .fold(BigRational::zero(), |total, (shares, price)| {
+                total + shares * price
+            });
+        result.values.push((*date, to_f64(&total_after)));
+        value_sum += &total_after;
+        previous_total = Some(total_after);
+    }
+
+    if value_sum > BigRational::zero() {
+        let average_value = value_sum / BigRational::from_integer(BigInt::from(dates.len()));
+        result.turnover = traded / BigRational::from_integer(BigInt::from(2)) / average_value;
+    }
+
+    result.final_value = previous_total.unwrap_or_else(BigRational::zero);
+    let days = (dates[dates.len() - 1] - start).num_days();
+    if days > 0 {
+        result.cagr = growth.powf(365.25 / days as f64) - 1.0;
+    }
+
+    result
+}
+
+fn round_to(amount: &BigRational, places: usize) -> BigRational {
+    let scale = BigRational::from_integer(num::pow(BigInt::from(10), places));
+    (amount * &scale).round() / scale
+}
+
+/// One strategy of a backtest as written by the csv export. Amounts are exact rationals written
+/// as strings like the plan's csv format, the drift is a fraction of the portfolio
+#[derive(Serialize)]
+pub struct StrategyResult {
+    pub strategy: String,
+    pub final_value: String,
+    pub contributed: String,
+    pub sold: String,
+    /// a fraction of the average portfolio value
+    pub turnover: String,
+    pub trades: usize,
+    pub max_drift: String,
+    pub cagr: f64,
+}
+
+impl From<&BacktestResult> for StrategyResult {
+    fn from(result: &BacktestResult) -> Self {
+        StrategyResult {
+            strategy: result.strategy.to_string(),
+            final_value: result.final_value.to_string(),
+            contributed: result.contributed.to_string(),
+            sold: result.sold.to_string(),
+            turnover: result.turnover.to_string(),
+            trades: result.trades,
+            max_drift: result.max_drift.to_string(),
+            cagr: result.cagr,
+        }
+    }
+}
+
+/// The cells of the backtest table: a header row and a row per strategy, amounts to the cent
+/// and the turnover, drift and CAGR in percent
+pub fn to_backtest_rows(results: &[BacktestResult]) -> Vec<Vec<String>> {
+    let mut rows: Vec<Vec<String>> = vec![[
+        "Strategy",
+        "Final value",
+        "Contributed",
+        "Sold",
+        "Turnover %",
+        "Trades",
+        "Max drift pts",
+        "CAGR %",
+    ]
+    .iter()
+    .map(|header| header.to_string())
+    .collect()];
+
+    let hundred = BigRational::from_integer(BigInt::from(100));
+    for result in results {
+        rows.push(vec![
+            result.strategy.to_string(),
+            format_rational(&result.final_value, 2),
+            format_rational(&result.contributed, 2),
+            format_rational(&result.sold, 2),
+            format_rational(&(&result.turnover * &hundred), 2),
+            result.trades.to_string(),
+            format_rational(&(&result.max_drift * &hundred), 2),
+            format_f64(result.cagr * 100.0, 2),
+        ]);
+    }
+
+    rows
+}
+
+/// What was replayed and over which dates
+pub fn backtest_summary(backtest: &Backtest, results: &[BacktestResult]) -> String {
+    let values = results.first().map_or(&[][..], |result| &result.values[..]);
+
+    match (values.first(), values.last()) {
+        (Some((start, _)), Some((end, _))) => format!("{} from {} to {}", backtest, start, end),
+        _ => backtest.to_string(),
+    }
+}
+
+/// The backtest as a tab aligned table below its summary
+pub fn backtest_to_string(backtest: &Backtest, results: &[BacktestResult]) -> String {
+    let buf = to_backtest_rows(results)
+        .iter()
+        .map(|row| row.join("\t"))
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    let mut tw = TabWriter::new(vec![]);
+
+    tw.write_all(buf.as_bytes()).unwrap();
+    tw.flush().unwrap();
+
+    format!(
+        "{}\n\n{}\n",
+        backtest_summary(backtest, results),
+        String::from_utf8(tw.into_inner().unwrap()).unwrap()
+    )
+}
+
+/// The results of the backtest in csv, one row per strategy
+pub fn backtest_to_csv(results: &[BacktestResult]) -> Result<String, Box<dyn std::error::Error>> {
+    let mut wtr = csv::Writer::from_writer(vec![]);
+    for result in results {
+        wtr.serialize(StrategyResult::from(result))?;
+    }
+
+    Ok(String::from_utf8(wtr.into_inner()?)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, fs};
+
+    fn amount(input: &str) -> BigRational {
+        parse_decimal(input).unwrap()
+    }
+
+    fn series(prices: &[(&str, &str)]) -> PriceSeries {
+        prices
+            .iter()
+            .map(|(date, price)| (date.parse().unwrap(), amount(price)))
+            .collect()
+    }
+
+    fn portfolio() -> Vec<Asset> {
+        vec![
+            Asset::new("Stocks".to_string(), amount("0.5"), amount("500")),
+            Asset::new("Bonds".to_string(), amount("0.5"), amount("500")),
+        ]
+    }
+
+    /// Reads price files where stocks double in the first month and then stay flat while bonds
+    /// stay flat throughout, then replays `contribution` monthly with the calendar strategy
+    /// rebalancing every month
+    fn doubling_stocks(contribution: &str) -> Vec<BacktestResult> {
+        let directory = env::temp_dir().join(format!("tui-rebalance-prices-{}", contribution));
+        let _ = fs::remove_dir_all(&directory);
+        fs::create_dir_all(&directory).unwrap();
+        fs::write(
+            directory.join("prices.csv"),
+            "Stocks, stocks.csv\nBonds, bonds.csv\n",
+        )
+        .unwrap();
+        // out of order and with a missing date that carries the last price forward
+        fs::write(
+            directory.join("stocks.csv"),
+            "2024-03-01, 2\n2024-01-01, 1\n2024-02-01, 2\n",
+        )
+        .unwrap();
+        fs::write(
+            directory.join("bonds.csv"),
+            "2024-01-01, 1\n2024-03-01, 1\n",
+        )
+        .unwrap();
+
+        let prices = read_prices(directory.join("prices.csv").to_str().unwrap());
+        fs::remove_dir_all(&directory).unwrap();
+
+        let backtest = Backtest {
+            amount: amount(contribution),
+            frequency: Frequency::Monthly,
+            calendar_months: 1,
+        };
+        run_backtest(
+            &portfolio(),
+            &prices.unwrap(),
+            &backtest,
+            BandTarget::Target,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn the_returns_of_the_price_files_are_replayed() {
+        let results = doubling_stocks("0");
+        let lazy = &results[0];
+        let calendar = &results[1];
+
+        let values: Vec<f64> = lazy.values.iter().map(|(_, value)| *value).collect();
+        assert_eq!(values, [1000.0, 1500.0, 1500.0]);
+        assert_eq!(lazy.final_value, amount("1500"));
+        assert_eq!(calendar.final_value, amount("1500"));
+        // the portfolio grew by half over the 60 days from January 1st to March 1st
+        assert!((lazy.cagr - 1.5f64.powf(365.25 / 60.0) + 1.0).abs() < 1e-9);
+        // stocks made up 2/3 of the portfolio once they doubled
+        assert_eq!(lazy.max_drift, amount("1") / amount("3"));
+        assert_eq!(lazy.trades, 0);
+        assert_eq!(calendar.trades, 2);
+    }
+
+    #[test]
+    fn contributions_are_not_counted_as_growth() {
+        let results = doubling_stocks("100");
+        let lazy = &results[0];
+
+        assert_eq!(lazy.contributed, amount("300"));
+        // half of the first $100 buys stocks that then double, the rest goes into bonds
+        assert_eq!(lazy.final_value, amount("1850"));
+        assert!((lazy.cagr - 1.5f64.powf(365.25 / 60.0) + 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn turnover_counts_the_buys_and_the_sells_once() {
+        let results = doubling_stocks("0");
+        let lazy = &results[0];
+        let calendar = &results[1];
+
+        assert!(lazy.turnover.is_zero());
+        // $250 of stocks swapped for bonds over an average value of $4000 / 3
+        assert_eq!(calendar.sold, amount("250"));
+        assert_eq!(calendar.turnover, amount("0.1875"));
+    }
+
+    #[test]
+    fn every_asset_needs_prices_over_common_dates() {
+        let backtest: Backtest = "100, monthly, 12".parse().unwrap();
+        let stocks_only: HashMap<String, PriceSeries> =
+            vec![("Stocks".to_string(), series(&[("2024-01-01", "1")]))]
+                .into_iter()
+                .collect();
+        let apart: HashMap<String, PriceSeries> = vec![
+            (
+                "Stocks".to_string(),
+                series(&[("2024-01-01", "1"), ("2024-02-01", "1")]),
+            ),
+            (
+                "Bonds".to_string(),
+                series(&[("2024-03-01", "1"), ("2024-04-01", "1")]),
+            ),
+        ]
+        .into_iter()
+        .collect();
+
+        let missing = run_backtest(&portfolio(), &stocks_only, &backtest, BandTarget::Target);
+        let no_overlap = run_backtest(&portfolio(), &apart, &backtest, BandTarget::Target);
+
+        assert_eq!(missing.err().unwrap(), "No prices for Bonds");
+        assert_eq!(
+            no_overlap.err().unwrap(),
+            "The price files have no dates in common"
+        );
+    }
+}
@@ -0,0 +1,170 @@
+use crate::error::{read_rows, LoadError, Row};
+use std::{
+    env,
+    path::{Path, PathBuf},
+};
+
+/// The files a portfolio is read from and saved back to
+#[derive(Clone)]
+pub struct PortfolioFiles {
+    pub targets: String,
+    pub portfolio: String,
+    /// column of the portfolio files holding the asset value, counted from 0
+    pub value_index: usize,
+    /// CSV listing the accounts, replaces `portfolio` when set
+    pub accounts: Option<String>,
+}
+
+impl Default for PortfolioFiles {
+    fn default() -> Self {
+        PortfolioFiles {
+            targets: "example/targets.csv".to_string(),
+            portfolio: "example/portfolio.csv".to_string(),
+            value_index: 1,
+            accounts: None,
+        }
+    }
+}
+
+impl PortfolioFiles {
+    /// The journal of executed rebalances, kept next to the accounts file or the portfolio,
+    /// e.g. `portfolio.csv` is journaled to `portfolio.journal.jsonl`
+    pub fn journal(&self) -> String {
+        let path = self.accounts.as_ref().unwrap_or(&self.portfolio);
+        Path::new(path)
+            .with_extension("journal.jsonl")
+            .to_string_lossy()
+            .to_string()
+    }
+
+    /// Where the TUI exports a contribution schedule, next to the journal, e.g.
+    /// `portfolio.schedule.csv`
+    pub fn schedule(&self) -> String {
+        let path = self.accounts.as_ref().unwrap_or(&self.portfolio);
+        Path::new(path)
+            .with_extension("schedule.csv")
+            .to_string_lossy()
+            .to_string()
+    }
+}
+
+/// The profiles file, `$XDG_CONFIG_HOME/tui-rebalance/profiles.csv` falling back to
+/// `~/.config/tui-rebalance/profiles.csv`
+pub fn profiles_path() -> Option<PathBuf> {
+    let config_home = env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+
+    Some(config_home.join("tui-rebalance").join("profiles.csv"))
+}
+
+/// Reads the named profile from the profiles file. Each row is a profile name, the targets
+/// file, the portfolio file and optionally the value column (counted from 1 like in a
+/// spreadsheet) and an accounts file. Paths may start with `~/`. A missing profiles file is the
+/// same as an empty one
+pub fn read_profile(name: &str) -> Result<Option<PortfolioFiles>, Vec<LoadError>> {
+    let path = match profiles_path() {
+        Some(path) if path.exists() => path.to_string_lossy().to_string(),
+        _ => return Ok(None),
+    };
+
+    let mut errors = vec![];
+    let rows = read_rows(&path, &mut errors);
+
+    let row = match rows.iter().find(|row| row.optional_text(0) == Some(name)) {
+        Some(row) => row,
+        None if errors.is_empty() => return Ok(None),
+        None => return Err(errors),
+    };
+
+    read_profile_row(row).map(Some).map_err(|error| vec![error])
+}
+
+fn read_profile_row(row: &Row) -> Result<PortfolioFiles, LoadError> {
+    let value_index = match row.optional_text(3) {
+        Some(_) => row.column_number(3)? - 1,
+        None => PortfolioFiles::default().value_index,
+    };
+
+    Ok(PortfolioFiles {
+        targets: expand_home(row.text(1)?),
+        portfolio: expand_home(row.text(2)?),
+        value_index,
+        accounts: row.optional_text(4).map(expand_home),
+    })
+}
+
+fn expand_home(path: &str) -> String {
+    match (path.strip_prefix("~/"), env::var_os("HOME")) {
+        (Some(rest), Some(home)) => PathBuf::from(home).join(rest).to_string_lossy().to_string(),
+        _ => path.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn the_journal_and_schedule_sit_next_to_the_accounts_or_portfolio() {
+        let mut files = PortfolioFiles {
+            portfolio: "savings/portfolio.csv".to_string(),
+            ..PortfolioFiles::default()
+        };
+        assert_eq!(files.journal(), "savings/portfolio.journal.jsonl");
+        assert_eq!(files.schedule(), "savings/portfolio.schedule.csv");
+
+        files.accounts = Some("household/accounts.csv".to_string());
+        assert_eq!(files.journal(), "household/accounts.journal.jsonl");
+        assert_eq!(files.schedule(), "household/accounts.schedule.csv");
+    }
+
+    #[test]
+    fn a_profile_row_names_the_files_and_value_column() {
+        let path = env::temp_dir()
+            .join("tui-rebalance-profiles.csv")
+            .to_string_lossy()
+            .to_string();
+        fs::write(
+            &path,
+            "retirement, ~/targets.csv, /data/ira.csv\n\
+             household, targets.csv, portfolio.csv, 3, accounts.csv\n\
+             broken, targets.csv, portfolio.csv, 1\n",
+        )
+        .unwrap();
+
+        let mut errors = vec![];
+        let rows = read_rows(&path, &mut errors);
+        fs::remove_file(&path).unwrap();
+
+        let retirement = read_profile_row(&rows[0]).unwrap();
+        assert_eq!(retirement.targets, expand_home("~/targets.csv"));
+        assert_eq!(retirement.portfolio, "/data/ira.csv");
+        assert_eq!(retirement.value_index, 1);
+        assert!(retirement.accounts.is_none());
+
+        let household = read_profile_row(&rows[1]).unwrap();
+        assert_eq!(household.value_index, 2);
+        assert_eq!(household.accounts.as_deref(), Some("accounts.csv"));
+
+        assert!(read_profile_row(&rows[2])
+            .err()
+            .unwrap()
+            .to_string()
+            .ends_with(":3:4: '1' is not a column number of 2 or more"));
+    }
+
+    #[test]
+    fn only_a_leading_tilde_is_the_home_directory() {
+        if let Some(home) = env::var_os("HOME") {
+            assert_eq!(
+                expand_home("~/targets.csv"),
+                PathBuf::from(home).join("targets.csv").to_string_lossy()
+            );
+        }
+        assert_eq!(expand_home("data/~/targets.csv"), "data/~/targets.csv");
+        assert_eq!(expand_home("~targets.csv"), "~targets.csv");
+    }
+}
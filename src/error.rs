@@ -0,0 +1,380 @@
+use crate::rebalance::parse_decimal;
+use chrono::NaiveDate;
+use num::BigRational;
+use std::{error::Error, fmt, fs, iter};
+
+/// A problem found while reading the targets, accounts, portfolio, journal or price files. Lines
+/// and columns count from 1 so they match what an editor shows
+#[derive(Debug)]
+pub enum LoadError {
+    /// the file couldn't be opened
+    Io { path: String, message: String },
+    /// a row the CSV reader couldn't make sense of
+    Csv {
+        path: String,
+        line: u64,
+        message: String,
+    },
+    /// a required column is missing or blank
+    MissingColumn {
+        path: String,
+        line: u64,
+        column: usize,
+    },
+    /// a column that should hold a dollar amount or a percentage doesn't
+    BadNumber {
+        path: String,
+        line: u64,
+        column: usize,
+        value: String,
+    },
+    /// a column number pointing at the asset name or not a number at all
+    BadColumn {
+        path: String,
+        line: u64,
+        column: usize,
+        value: String,
+    },
+    /// a journal line that isn't a valid entry
+    BadEntry {
+        path: String,
+        line: u64,
+        message: String,
+    },
+    /// a column that should hold a date like 2024-01-31 doesn't
+    BadDate {
+        path: String,
+        line: u64,
+        column: usize,
+        value: String,
+    },
+    /// a target allocation outside of 0 to 100 percent
+    OutOfRange {
+        path: String,
+        line: u64,
+        column: usize,
+        value: String,
+    },
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LoadError::Io { path, message } => write!(f, "{}: {}", path, message),
+            LoadError::Csv {
+                path,
+                line,
+                message,
+            } => write!(f, "{}:{}: {}", path, line, message),
+            LoadError::MissingColumn { path, line, column } => {
+                write!(f, "{}:{}:{}: missing value", path, line, column)
+            }
+            LoadError::BadNumber {
+                path,
+                line,
+                column,
+                value,
+            } => write!(
+                f,
+                "{}:{}:{}: '{}' is not a number",
+                path, line, column, value
+            ),
+            LoadError::BadColumn {
+                path,
+                line,
+                column,
+                value,
+            } => write!(
+                f,
+                "{}:{}:{}: '{}' is not a column number of 2 or more",
+                path, line, column, value
+            ),
+            LoadError::BadEntry {
+                path,
+                line,
+                message,
+            } => write!(f, "{}:{}: {}", path, line, message),
+            LoadError::BadDate {
+                path,
+                line,
+                column,
+                value,
+            } => write!(
+                f,
+                "{}:{}:{}: '{}' is not a date like 2024-01-31",
+                path, line, column, value
+            ),
+            LoadError::OutOfRange {
+                path,
+                line,
+                column,
+                value,
+            } => write!(
+                f,
+                "{}:{}:{}: '{}' must be between 0 and 100",
+                path, line, column, value
+            ),
+        }
+    }
+}
+
+impl Error for LoadError {}
+
+/// A row of a CSV file that remembers where it came from so bad columns can be reported.
+/// Columns are indexed from 0 like `csv::StringRecord`
+pub struct Row<'a> {
+    path: &'a str,
+    line: u64,
+    record: csv::StringRecord,
+}
+
+impl<'a> Row<'a> {
+    /// The trimmed text of a column that has to be filled in
+    pub fn text(&self, column: usize) -> Result<&str, LoadError> {
+        self.optional_text(column)
+            .ok_or_else(|| LoadError::MissingColumn {
+                path: self.path.to_string(),
+                line: self.line,
+                column: column + 1,
+            })
+    }
+
+    /// The trimmed text of a column that may be left out or blank
+    pub fn optional_text(&self, column: usize) -> Option<&str> {
+        self.record
+            .get(column)
+            .map(str::trim)
+            .filter(|text| !text.is_empty())
+    }
+
+    /// An error about the row as a whole rather than one of its columns
+    pub fn bad_entry(&self, message: String) -> LoadError {
+        LoadError::BadEntry {
+            path: self.path.to_string(),
+            line: self.line,
+            message,
+        }
+    }
+
+    /// The number of columns of the row, blank ones included
+    pub fn columns(&self) -> usize {
+        self.record.len()
+    }
+
+    pub fn decimal(&self, column: usize) -> Result<BigRational, LoadError> {
+        self.to_decimal(column, self.text(column)?)
+    }
+
+    pub fn optional_decimal(&self, column: usize) -> Result<Option<BigRational>, LoadError> {
+        self.optional_text(column)
+            .map(|text| self.to_decimal(column, text))
+            .transpose()
+    }
+
+    fn to_decimal(&self, column: usize, text: &str) -> Result<BigRational, LoadError> {
+        parse_decimal(text).ok_or_else(|| LoadError::BadNumber {
+            path: self.path.to_string(),
+            line: self.line,
+            column: column + 1,
+            value: text.to_string(),
+        })
+    }
+
+    /// A date column written as year-month-day, e.g. `2024-01-31`
+    pub fn date(&self, column: usize) -> Result<NaiveDate, LoadError> {
+        let text = self.text(column)?;
+
+        NaiveDate::parse_from_str(text, "%Y-%m-%d").map_err(|_| LoadError::BadDate {
+            path: self.path.to_string(),
+            line: self.line,
+            column: column + 1,
+            value: text.to_string(),
+        })
+    }
+
+    /// A column holding the number of another column, counted from 1. Column 1 is the asset
+    /// name so the number has to be 2 or more
+    pub fn column_number(&self, column: usize) -> Result<usize, LoadError> {
+        let text = self.text(column)?;
+
+        match text.parse::<usize>() {
+            Ok(number) if number >= 2 => Ok(number),
+            _ => Err(LoadError::BadColumn {
+                path: self.path.to_string(),
+                line: self.line,
+                column: column + 1,
+                value: text.to_string(),
+            }),
+        }
+    }
+
+    /// A percentage column that has to lie between 0 and 100
+    pub fn percent(&self, column: usize) -> Result<BigRational, LoadError> {
+        let percent = self.decimal(column)?;
+
+        if percent < BigRational::from_integer(0.into())
+            || percent > BigRational::from_integer(100.into())
+        {
+            return Err(LoadError::OutOfRange {
+                path: self.path.to_string(),
+                line: self.line,
+                column: column + 1,
+                value: self.text(column)?.to_string(),
+            });
+        }
+
+        Ok(percent)
+    }
+}
+
+/// Reads every row of a header-less CSV file, lines starting with '#' are comments. Rows the
+/// CSV reader rejects and an unreadable file are added to `errors` and left out
+pub fn read_rows<'a>(path: &'a str, errors: &mut Vec<LoadError>) -> Vec<Row<'a>> {
+    let contents = match fs::read(path) {
+        Ok(contents) => contents,
+        Err(error) => {
+            errors.push(LoadError::Io {
+                path: path.to_string(),
+                message: error.to_string(),
+            });
+            return vec![];
+        }
+    };
+
+    // comments are blanked rather than left to the CSV reader so they are skipped like any
+    // empty line
+    let contents = contents
+        .split(|byte| *byte == b'\n')
+        .map(|line| {
+            if line.starts_with(b"#") {
+                &[][..]
+            } else {
+                line
+            }
+        })
+        .collect::<Vec<&[u8]>>()
+        .join(&b'\n');
+    let line_starts: Vec<usize> = iter::once(0)
+        .chain(
+            contents
+                .iter()
+                .enumerate()
+                .filter(|(_, byte)| **byte == b'\n')
+                .map(|(index, _)| index + 1),
+        )
+        .collect();
+
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .from_reader(&contents[..]);
+
+    let mut rows = vec![];
+
+    for result in reader.records() {
+        match result {
+            Ok(record) => rows.push(Row {
+                path,
+                line: record_line(&contents, &line_starts, record.position()),
+                record,
+            }),
+            Err(error) => errors.push(LoadError::Csv {
+                path: path.to_string(),
+                line: record_line(&contents, &line_starts, error.position()),
+                message: error.to_string(),
+            }),
+        }
+    }
+
+    rows
+}
+
+/// The line a record starts on, worked out from its byte offset. The reader's own line numbers
+/// leave out the empty lines in front of a record and go wrong with Windows line endings
+fn record_line(contents: &[u8], line_starts: &[usize], position: Option<&csv::Position>) -> u64 {
+    let mut offset = match position {
+        Some(position) => position.byte() as usize,
+        None => return 0,
+    };
+
+    // the offset can point at the line break that ended the previous record
+    while matches!(contents.get(offset), Some(b'\r' | b'\n')) {
+        offset += 1;
+    }
+
+    line_starts.partition_point(|start| *start <= offset) as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, fs};
+
+    fn row(columns: Vec<&str>) -> Row<'static> {
+        Row {
+            path: "targets.csv",
+            line: 3,
+            record: csv::StringRecord::from(columns),
+        }
+    }
+
+    #[test]
+    fn a_percent_outside_0_to_100_is_reported_at_its_line_and_column() {
+        let row = row(vec!["Bond fund", " 120 ", "-5", "40", "100"]);
+
+        assert_eq!(
+            row.percent(1).unwrap_err().to_string(),
+            "targets.csv:3:2: '120' must be between 0 and 100"
+        );
+        assert_eq!(
+            row.percent(2).unwrap_err().to_string(),
+            "targets.csv:3:3: '-5' must be between 0 and 100"
+        );
+        assert_eq!(row.percent(3).unwrap(), parse_decimal("40").unwrap());
+        assert_eq!(row.percent(4).unwrap(), parse_decimal("100").unwrap());
+    }
+
+    #[test]
+    fn missing_and_bad_columns_are_reported_at_their_line_and_column() {
+        let row = row(vec!["Bond fund", "", "ten", "1"]);
+
+        assert_eq!(
+            row.decimal(1).unwrap_err().to_string(),
+            "targets.csv:3:2: missing value"
+        );
+        assert_eq!(
+            row.decimal(2).unwrap_err().to_string(),
+            "targets.csv:3:3: 'ten' is not a number"
+        );
+        assert_eq!(
+            row.column_number(3).unwrap_err().to_string(),
+            "targets.csv:3:4: '1' is not a column number of 2 or more"
+        );
+        assert_eq!(row.optional_decimal(1).unwrap(), None);
+        assert_eq!(row.optional_text(9), None);
+    }
+
+    #[test]
+    fn rows_keep_their_line_in_the_file() {
+        let path = env::temp_dir()
+            .join("tui-rebalance-rows.csv")
+            .to_string_lossy()
+            .to_string();
+        let mut errors = vec![];
+        let mut lines = |contents: &str| -> Vec<u64> {
+            fs::write(&path, contents).unwrap();
+            read_rows(&path, &mut errors)
+                .iter()
+                .map(|row| row.line)
+                .collect()
+        };
+
+        let unix = lines("# targets\nBond fund, 40\n\nStocks, 60\n");
+        let windows = lines("\r\n# targets\r\nBond fund, 40\r\n\r\nStocks, 60\r\n");
+        fs::remove_file(&path).unwrap();
+
+        assert!(errors.is_empty());
+        assert_eq!(unix, vec![2, 4]);
+        assert_eq!(windows, vec![3, 5]);
+    }
+}
@@ -0,0 +1,296 @@
+use crate::error::LoadError;
+use crate::rebalance::{format_rational, Asset, RebalancePlan};
+use crate::report::{to_results, AssetResult};
+use chrono::Local;
+use num::{BigInt, BigRational, Zero};
+use serde::{Deserialize, Serialize};
+use std::{
+    error::Error,
+    fs::{File, OpenOptions},
+    io::{self, BufRead, BufReader, ErrorKind, Read, Seek, SeekFrom, Write},
+    str::FromStr,
+};
+use tabwriter::TabWriter;
+
+/// One executed rebalance or withdrawal as recorded in the journal
+#[derive(Serialize, Deserialize)]
+pub struct JournalEntry {
+    /// local time the trades were executed, e.g. `2026-10-17 14:05`
+    pub date: String,
+    /// what was run, e.g. `Rebalance (lazy)` or `Withdraw $500.00`
+    pub action: String,
+    /// cash added to the portfolio as an exact rational, negative for a withdrawal
+    pub contribution: String,
+    /// the account that traded, None for a single portfolio
+    pub account: Option<String>,
+    /// every asset with its value before the trades, its trade and its allocation after them
+    pub assets: Vec<AssetResult>,
+}
+
+impl JournalEntry {
+    /// The entry for executing `plan` on the portfolio `assets` it was made for
+    pub fn new(
+        action: String,
+        account: Option<String>,
+        assets: &[Asset],
+        plan: &RebalancePlan,
+    ) -> Self {
+        JournalEntry {
+            date: Local::now().format("%Y-%m-%d %H:%M").to_string(),
+            action,
+            contribution: plan.contribution.to_string(),
+            account,
+            assets: to_results(assets, plan),
+        }
+    }
+}
+
+/// Reads every entry of a journal file, one JSON object per line. A missing journal is an
+/// empty one. Lines that can't be read, such as one cut short by a crash, are skipped and
+/// returned as warnings along with the entries that could be read
+pub fn read_journal(path: &str) -> (Vec<JournalEntry>, Vec<LoadError>) {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(error) if error.kind() == ErrorKind::NotFound => return (vec![], vec![]),
+        Err(error) => {
+            let error = LoadError::Io {
+                path: path.to_string(),
+                message: error.to_string(),
+            };
+            return (vec![], vec![error]);
+        }
+    };
+
+    let mut journal = vec![];
+    let mut errors = vec![];
+
+    for (index, line) in BufReader::new(file).lines().enumerate() {
+        let parsed = line
+            .map_err(|error| error.to_string())
+            .and_then(|line| match line.trim() {
+                "" => Ok(None),
+                line => serde_json::from_str(line)
+                    .map(Some)
+                    .map_err(|error| error.to_string()),
+            });
+
+        match parsed {
+            Ok(Some(entry)) => journal.push(entry),
+            Ok(None) => {}
+            Err(message) => errors.push(LoadError::BadEntry {
+                path: path.to_string(),
+                line: index as u64 + 1,
+                message,
+            }),
+        }
+    }
+
+    (journal, errors)
+}
+
+/// Appends an entry to the end of the journal, creating the file for the first entry. A line
+/// left unfinished by an earlier crash is ended first so the entry starts a line of its own,
+/// and the file is synced before returning
+pub fn append_entry(path: &str, entry: &JournalEntry) -> Result<(), Box<dyn Error>> {
+    let mut file = OpenOptions::new()
+        .create(true)
+        .read(true)
+        .append(true)
+        .open(path)?;
+
+    let mut line = serde_json::to_string(entry)?;
+    line.push('\n');
+    if ends_unfinished(&mut file)? {
+        line.insert(0, '\n');
+    }
+
+    file.write_all(line.as_bytes())?;
+    file.sync_all()?;
+
+    Ok(())
+}
+
+/// Whether the file has a last line without a newline
+fn ends_unfinished(file: &mut File) -> io::Result<bool> {
+    if file.metadata()?.len() == 0 {
+        return Ok(false);
+    }
+
+    let mut last = [0; 1];
+    file.seek(SeekFrom::End(-1))?;
+    file.read_exact(&mut last)?;
+
+    Ok(last[0] != b'\n')
+}
+
+/// One row per entry: number, date, action, amount and account
+pub fn to_history_rows(journal: &[JournalEntry]) -> Vec<Vec<String>> {
+    journal
+        .iter()
+        .enumerate()
+        .map(|(index, entry)| {
+            vec![
+                (index + 1).to_string(),
+                entry.date.clone(),
+                entry.action.clone(),
+                format_amount(&entry.contribution),
+                entry.account.clone().unwrap_or_default(),
+            ]
+        })
+        .collect()
+}
+
+/// The journal as a tab aligned table for the `history` subcommand
+pub fn history_to_string(journal: &[JournalEntry]) -> String {
+    let mut rows = to_history_rows(journal);
+    rows.insert(
+        0,
+        ["#", "Date", "Action", "Amount", "Account"]
+            .iter()
+            .map(|header| header.to_string())
+            .collect(),
+    );
+
+    let buf = rows
+        .iter()
+        .map(|row| row.join("\t"))
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    let mut tw = TabWriter::new(vec![]);
+
+    tw.write_all(buf.as_bytes()).unwrap();
+    tw.flush().unwrap();
+
+    String::from_utf8(tw.into_inner().unwrap()).unwrap()
+}
+
+/// The trades of one entry: asset, value before, trade and allocation after in percent
+pub fn entry_rows(entry: &JournalEntry) -> Vec<Vec<String>> {
+    entry
+        .assets
+        .iter()
+        .map(|asset| {
+            vec![
+                asset.name.clone(),
+                format_amount(&asset.value),
+                format_amount(&asset.delta),
+                format_percent(parse(&asset.new_allocation).as_ref()),
+            ]
+        })
+        .collect()
+}
+
+/// How the portfolio moved between two entries: asset, allocation after each of them in
+/// percent, the change in percentage points and the value after each of them
+pub fn diff_rows(from: &JournalEntry, to: &JournalEntry) -> Vec<Vec<String>> {
+    let mut names: Vec<&str> = from
+        .assets
+        .iter()
+        .map(|asset| asset.name.as_str())
+        .collect();
+    for asset in &to.assets {
+        if !names.contains(&asset.name.as_str()) {
+            names.push(&asset.name);
+        }
+    }
+
+    names
+        .into_iter()
+        .map(|name| {
+            let before = find_asset(from, name);
+            let after = find_asset(to, name);
+            let allocation = |asset: Option<&AssetResult>| {
+                asset.map_or_else(
+                    || Some(BigRational::zero()),
+                    |asset| parse(&asset.new_allocation),
+                )
+            };
+            let change = match (allocation(before), allocation(after)) {
+                (Some(before), Some(after)) => Some(after - before),
+                _ => None,
+            };
+
+            vec![
+                name.to_string(),
+                format_percent(allocation(before).as_ref()),
+                format_percent(allocation(after).as_ref()),
+                format_percent(change.as_ref()),
+                format_value_after(before),
+                format_value_after(after),
+            ]
+        })
+        .collect()
+}
+
+fn find_asset<'a>(entry: &'a JournalEntry, name: &str) -> Option<&'a AssetResult> {
+    entry.assets.iter().find(|asset| asset.name == name)
+}
+
+/// Journal amounts are rationals written as strings such as `33001/2`
+fn parse(text: &str) -> Option<BigRational> {
+    BigRational::from_str(text).ok()
+}
+
+/// Shows an amount to the cent, or as written when the journal was edited by hand
+fn format_amount(text: &str) -> String {
+    parse(text).map_or_else(|| text.to_string(), |amount| format_rational(&amount, 2))
+}
+
+fn format_percent(fraction: Option<&BigRational>) -> String {
+    let hundred = BigRational::from_integer(BigInt::from(100));
+    fraction.map_or_else(String::new, |fraction| {
+        format_rational(&(fraction * hundred), 3)
+    })
+}
+
+/// The value of the asset once the entry's trade was executed
+fn format_value_after(asset: Option<&AssetResult>) -> String {
+    let value = match asset {
+        Some(asset) => match (parse(&asset.value), parse(&asset.delta)) {
+            (Some(value), Some(delta)) => value + delta,
+            _ => return String::new(),
+        },
+        None => BigRational::zero(),
+    };
+
+    format_rational(&value, 2)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rebalance::{lazy_rebalance, parse_decimal};
+    use std::{env, fs};
+
+    fn entry() -> JournalEntry {
+        let assets = vec![Asset::new(
+            "Bond fund".to_string(),
+            parse_decimal("1").unwrap(),
+            parse_decimal("100").unwrap(),
+        )];
+        let plan = lazy_rebalance(&parse_decimal("50").unwrap(), &assets);
+
+        JournalEntry::new("Rebalance (lazy)".to_string(), None, &assets, &plan)
+    }
+
+    #[test]
+    fn a_line_cut_short_is_skipped_and_the_next_entry_starts_a_new_line() {
+        let path = env::temp_dir().join("tui-rebalance-partial.journal.jsonl");
+        let path = path.to_str().unwrap();
+        let _ = fs::remove_file(path);
+
+        append_entry(path, &entry()).unwrap();
+        // a crash in the middle of writing the second entry
+        let mut file = OpenOptions::new().append(true).open(path).unwrap();
+        write!(file, "{{\"date\":\"2026-").unwrap();
+        append_entry(path, &entry()).unwrap();
+
+        let (journal, warnings) = read_journal(path);
+        fs::remove_file(path).unwrap();
+
+        assert_eq!(journal.len(), 2);
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].to_string().contains(":2:"));
+    }
+}
@@ -0,0 +1,23 @@
+//! The rebalancing engine behind tui-rebalance: reading the targets and portfolio files,
+//! planning lazy, full and band rebalances with exact arithmetic and formatting the results.
+//! Nothing here depends on the terminal, the TUI binary is built on top with the `terminal`
+//! feature
+
+pub mod backtest;
+pub mod config;
+pub mod error;
+pub mod journal;
+pub mod portfolio;
+pub mod projection;
+pub mod rebalance;
+pub mod report;
+pub mod schedule;
+mod store;
+pub mod targets;
+
+pub use config::PortfolioFiles;
+pub use error::LoadError;
+pub use portfolio::{Account, Portfolio, Target};
+pub use rebalance::{lazy_rebalance, Asset, RebalancePlan};
+pub use report::{render, OutputFormat};
+pub use targets::{create_target_tree, TargetNode};
@@ -1,4 +1,3 @@
-#[allow(dead_code)]
 mod util;
 
 use crate::util::{
@@ -6,6 +5,8 @@ use crate::util::{
     event::{Event, Events},
     ui,
 };
+use clap::{Arg, ArgMatches, SubCommand};
+use num::{BigRational, Zero};
 use regex::Regex;
 use std::{
     error::Error,
@@ -13,9 +14,412 @@ use std::{
 };
 use termion::{event::Key, input::MouseTerminal, raw::IntoRawMode, screen::AlternateScreen};
 use tui::{backend::TermionBackend, Terminal};
-use util::app::InputMode;
+use tui_rebalance::{
+    backtest::{backtest_to_csv, backtest_to_string, read_prices, Backtest},
+    config::{profiles_path, read_profile, PortfolioFiles},
+    error::LoadError,
+    journal::{history_to_string, read_journal},
+    projection::{projection_to_csv, projection_to_string, read_assumptions, Projection},
+    rebalance::{parse_decimal, BandTarget, RebalanceMode},
+    report::{render, OutputFormat},
+    schedule::{read_returns, schedule_to_csv, schedule_to_string, Frequency, Schedule},
+    targets::unbalanced_groups,
+    Portfolio,
+};
+use util::app::{Focus, InputMode};
 
 fn main() -> Result<(), Box<dyn Error>> {
+    let matches = clap::App::new("tui-rebalance")
+        .about("Optimal lazy portfolio rebalancing calculator")
+        .arg(
+            Arg::with_name("mode")
+                .long("mode")
+                .value_name("MODE")
+                .help("How the rebalance amount is spread across the assets")
+                .possible_values(&["lazy", "full", "band"])
+                .default_value("lazy")
+                .global(true),
+        )
+        .arg(
+            Arg::with_name("band-target")
+                .long("band-target")
+                .value_name("TARGET")
+                .help("Where a band rebalance moves out of band assets to")
+                .possible_values(&["edge", "target"])
+                .default_value("target")
+                .global(true),
+        )
+        .arg(
+            Arg::with_name("absolute-band")
+                .long("absolute-band")
+                .value_name("PERCENT")
+                .help("Default absolute tolerance band in percentage points e.g. 5")
+                .global(true),
+        )
+        .arg(
+            Arg::with_name("relative-band")
+                .long("relative-band")
+                .value_name("PERCENT")
+                .help("Default relative tolerance band in percent of the target e.g. 25")
+                .global(true),
+        )
+        .arg(
+            Arg::with_name("profile")
+                .long("profile")
+                .value_name("NAME")
+                .help("Portfolio profile from the profiles file, 'default' is used when present")
+                .global(true),
+        )
+        .arg(
+            Arg::with_name("targets")
+                .long("targets")
+                .value_name("FILE")
+                .help("CSV of the target allocations [default: example/targets.csv]")
+                .global(true),
+        )
+        .arg(
+            Arg::with_name("portfolio")
+                .long("portfolio")
+                .value_name("FILE")
+                .help("CSV of the portfolio holdings [default: example/portfolio.csv]")
+                .global(true),
+        )
+        .arg(
+            Arg::with_name("value-column")
+                .long("value-column")
+                .value_name("N")
+                .help("Column of the portfolio files holding the asset value [default: 2]")
+                .global(true),
+        )
+        .arg(
+            Arg::with_name("accounts")
+                .long("accounts")
+                .value_name("FILE")
+                .help("CSV listing each account name and the portfolio file that holds it")
+                .global(true),
+        )
+        .arg(
+            Arg::with_name("min-trade")
+                .long("min-trade")
+                .value_name("AMOUNT")
+                .help("Drop lazy rebalance trades smaller than this dollar amount")
+                .global(true),
+        )
+        .arg(
+            Arg::with_name("returns")
+                .long("returns")
+                .value_name("FILE")
+                .help("CSV of the expected annual return of each asset in percent, for schedules")
+                .global(true),
+        )
+        .arg(
+            Arg::with_name("prices")
+                .long("prices")
+                .value_name("FILE")
+                .help("CSV naming the price history file of each asset, for backtests")
+                .global(true),
+        )
+        .arg(
+            Arg::with_name("assumptions")
+                .long("assumptions")
+                .value_name("FILE")
+                .help("CSV of the expected return, volatility and correlations of each asset")
+                .global(true),
+        )
+        .arg(
+            Arg::with_name("paths")
+                .long("paths")
+                .value_name("N")
+                .help("Number of paths a projection simulates [default: 1000]")
+                .global(true),
+        )
+        .arg(
+            Arg::with_name("seed")
+                .long("seed")
+                .value_name("N")
+                .help("Seed of the random numbers of a projection [default: 1]")
+                .global(true),
+        )
+        .subcommand(
+            SubCommand::with_name("validate")
+                .about("Checks the targets and portfolio files and prints every problem found"),
+        )
+        .subcommand(
+            SubCommand::with_name("history")
+                .about("Lists the rebalances recorded in the journal next to the portfolio"),
+        )
+        .subcommand(
+            SubCommand::with_name("plan")
+                .about("Prints the rebalance report for a contribution and exits")
+                .arg(
+                    Arg::with_name("contribute")
+                        .long("contribute")
+                        .value_name("AMOUNT")
+                        .help("Dollar amount to add to the portfolio, negative to withdraw")
+                        .allow_hyphen_values(true)
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("account")
+                        .long("account")
+                        .value_name("NAME")
+                        .help("Account receiving the contribution when there are several"),
+                )
+                .arg(
+                    Arg::with_name("format")
+                        .long("format")
+                        .value_name("FORMAT")
+                        .help("How the results are printed")
+                        .possible_values(&["table", "markdown", "json", "csv"])
+                        .default_value("table"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("schedule")
+                .about("Prints the purchases of a contribution made every period and exits")
+                .arg(
+                    Arg::with_name("amount")
+                        .long("amount")
+                        .value_name("AMOUNT")
+                        .help("Dollar amount contributed every period")
+                        .required_unless("total")
+                        .conflicts_with("total"),
+                )
+                .arg(
+                    Arg::with_name("total")
+                        .long("total")
+                        .value_name("AMOUNT")
+                        .help("Dollar amount spread evenly over every period"),
+                )
+                .arg(
+                    Arg::with_name("every")
+                        .long("every")
+                        .value_name("FREQUENCY")
+                        .help("How often the contributions are made")
+                        .possible_values(&["weekly", "biweekly", "monthly"])
+                        .default_value("monthly"),
+                )
+                .arg(
+                    Arg::with_name("months")
+                        .long("months")
+                        .value_name("N")
+                        .help("How many months the contributions are made for")
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("format")
+                        .long("format")
+                        .value_name("FORMAT")
+                        .help("How the purchases are printed")
+                        .possible_values(&["table", "csv"])
+                        .default_value("table"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("backtest")
+                .about("Replays a contribution over the price history and exits")
+                .arg(
+                    Arg::with_name("amount")
+                        .long("amount")
+                        .value_name("AMOUNT")
+                        .help("Dollar amount contributed every period")
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("every")
+                        .long("every")
+                        .value_name("FREQUENCY")
+                        .help("How often the contributions are made")
+                        .possible_values(&["weekly", "biweekly", "monthly"])
+                        .default_value("monthly"),
+                )
+                .arg(
+                    Arg::with_name("rebalance-every")
+                        .long("rebalance-every")
+                        .value_name("MONTHS")
+                        .help("Months between the full rebalances of the calendar strategy")
+                        .default_value("12"),
+                )
+                .arg(
+                    Arg::with_name("format")
+                        .long("format")
+                        .value_name("FORMAT")
+                        .help("How the results are printed")
+                        .possible_values(&["table", "csv"])
+                        .default_value("table"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("project")
+                .about("Simulates the future value of the portfolio and exits")
+                .arg(
+                    Arg::with_name("amount")
+                        .long("amount")
+                        .value_name("AMOUNT")
+                        .help("Dollar amount contributed every period")
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("every")
+                        .long("every")
+                        .value_name("FREQUENCY")
+                        .help("How often the contributions are made")
+                        .possible_values(&["weekly", "biweekly", "monthly"])
+                        .default_value("monthly"),
+                )
+                .arg(
+                    Arg::with_name("years")
+                        .long("years")
+                        .value_name("N")
+                        .help("How many years are projected")
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("goal")
+                        .long("goal")
+                        .value_name("AMOUNT")
+                        .help("Portfolio value to report the chance of reaching")
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("rebalance-every")
+                        .long("rebalance-every")
+                        .value_name("MONTHS")
+                        .help("Months between full rebalances of the portfolio")
+                        .default_value("12"),
+                )
+                .arg(
+                    Arg::with_name("format")
+                        .long("format")
+                        .value_name("FORMAT")
+                        .help("How the percentiles are printed")
+                        .possible_values(&["table", "csv"])
+                        .default_value("table"),
+                ),
+        )
+        .get_matches();
+
+    // global options may be given before or after the subcommand
+    let args = match matches.subcommand() {
+        (_, Some(subcommand)) => subcommand,
+        _ => &matches,
+    };
+
+    let files = portfolio_files(args).unwrap_or_else(|error| {
+        eprintln!("{}", error);
+        std::process::exit(1);
+    });
+
+    if matches.subcommand_matches("validate").is_some() {
+        match Portfolio::load(&files) {
+            Ok(portfolio) => {
+                let problems = unbalanced_groups(&portfolio.target_tree);
+                if problems.is_empty() {
+                    println!("No problems found");
+                    return Ok(());
+                }
+
+                for problem in &problems {
+                    eprintln!("{}, targets must add up to 100%", problem);
+                }
+                std::process::exit(1);
+            }
+            Err(errors) => {
+                for error in &errors {
+                    eprintln!("{}", error);
+                }
+                std::process::exit(1);
+            }
+        }
+    }
+
+    if matches.subcommand_matches("history").is_some() {
+        let (journal, warnings) = read_journal(&files.journal());
+        for warning in &warnings {
+            eprintln!("Skipped {}", warning);
+        }
+        println!("{}", history_to_string(&journal));
+        return Ok(());
+    }
+
+    let rebalance_mode: RebalanceMode = args.value_of("mode").unwrap().parse()?;
+    let band_target: BandTarget = args.value_of("band-target").unwrap().parse()?;
+    let decimal_arg = |name: &str| {
+        args.value_of(name)
+            .map(|value| parse_decimal(value).ok_or_else(|| format!("--{} must be a number", name)))
+            .transpose()
+    };
+    let absolute_band = decimal_arg("absolute-band")?;
+    let minimum_trade = decimal_arg("min-trade")?;
+    let relative_band = decimal_arg("relative-band")?;
+
+    let mut app = App::new(&files);
+    app.rebalance_mode = rebalance_mode;
+    app.band_target = band_target;
+    app.set_default_bands(absolute_band.as_ref(), relative_band.as_ref());
+    app.minimum_trade = minimum_trade;
+    if let Some(path) = args.value_of("returns") {
+        match read_returns(path) {
+            Ok(returns) => app.expected_returns = returns,
+            Err(errors) => app.load_errors.extend(errors),
+        }
+    }
+    if let Some(path) = args.value_of("prices") {
+        match read_prices(path) {
+            Ok(prices) => app.prices = prices,
+            Err(errors) => app.load_errors.extend(errors),
+        }
+    }
+    if let Some(path) = args.value_of("assumptions") {
+        match read_assumptions(path) {
+            Ok(assumptions) => app.assumptions = assumptions,
+            Err(errors) => app.load_errors.extend(errors),
+        }
+    }
+    if let Some(paths) = args.value_of("paths") {
+        app.simulation.paths = match paths.parse::<usize>() {
+            Ok(paths) if paths > 0 => paths,
+            _ => return Err(format!("--paths must be a number of paths, not '{}'", paths).into()),
+        };
+    }
+    if let Some(seed) = args.value_of("seed") {
+        app.simulation.seed = seed
+            .parse()
+            .map_err(|_| format!("--seed must be a whole number, not '{}'", seed))?;
+    }
+
+    if let Some(plan) = matches.subcommand_matches("plan") {
+        if let Err(error) = print_plan(&mut app, plan) {
+            eprintln!("{}", error);
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
+    if let Some(schedule) = matches.subcommand_matches("schedule") {
+        if let Err(error) = print_schedule(&mut app, schedule) {
+            eprintln!("{}", error);
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
+    if let Some(backtest) = matches.subcommand_matches("backtest") {
+        if let Err(error) = print_backtest(&mut app, backtest) {
+            eprintln!("{}", error);
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
+    if let Some(projection) = matches.subcommand_matches("project") {
+        if let Err(error) = print_projection(&mut app, projection) {
+            eprintln!("{}", error);
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
     // Terminal initialization
     let stdout = io::stdout().into_raw_mode()?;
     let stdout = MouseTerminal::from(stdout);
@@ -25,10 +429,13 @@ fn main() -> Result<(), Box<dyn Error>> {
 
     let mut events = Events::new();
 
-    let mut app = App::new();
-    //not used right now but will eventually be used to edit portfolio target allocations
-    let mut toggle: bool = false;
-
+    if !app.load_errors.is_empty() {
+        app.error_msg = format!(
+            "Found {} problem(s) in the portfolio files",
+            app.load_errors.len()
+        );
+        app.input_mode = InputMode::ErrorDisplay;
+    }
     // Validate that input matches a dollar amount e.g. 1000.00
     let input_validation = Regex::new(r"^\d+[.]?\d{2}?$").unwrap();
 
@@ -39,7 +446,11 @@ fn main() -> Result<(), Box<dyn Error>> {
         match events.next()? {
             Event::Input(input) => match app.input_mode {
                 util::app::InputMode::Normal => match input {
-                    Key::Char('e') => {
+                    Key::Char('e') if matches!(app.focus, Focus::Portfolio | Focus::Targets) => {
+                        // start from the current percentage of the selected target
+                        if app.focus == Focus::Targets {
+                            app.input = app.selected_target_percent().unwrap_or_default();
+                        }
                         app.input_mode = util::app::InputMode::Editing;
                         events.disable_exit_key();
                     }
@@ -47,59 +458,138 @@ fn main() -> Result<(), Box<dyn Error>> {
                         app.input_mode = util::app::InputMode::Exec;
                         events.disable_exit_key();
                     }
+                    Key::Char('w') => {
+                        app.input_mode = util::app::InputMode::Withdraw;
+                        events.disable_exit_key();
+                    }
+                    Key::Char('i') => {
+                        app.input_mode = util::app::InputMode::Insert;
+                        events.disable_exit_key();
+                    }
+                    Key::Char('s') => {
+                        app.input_mode = util::app::InputMode::Schedule;
+                        events.disable_exit_key();
+                    }
+                    Key::Char('b') => {
+                        app.input_mode = util::app::InputMode::Backtest;
+                        events.disable_exit_key();
+                    }
+                    Key::Char('p') => {
+                        app.input_mode = util::app::InputMode::Projection;
+                        events.disable_exit_key();
+                    }
+                    Key::Char('x') if app.focus == Focus::Schedule => {
+                        if let Err(error_msg) = app.export_schedule() {
+                            app.error_msg = error_msg;
+                            app.input_mode = util::app::InputMode::ErrorDisplay;
+                            events.disable_exit_key();
+                        }
+                    }
+                    // rename and delete act on the asset selected in the portfolio
+                    Key::Char('n') if app.focus == Focus::Portfolio => {
+                        if let Some(name) = app.selected_asset() {
+                            app.input = name;
+                            app.input_mode = util::app::InputMode::Rename;
+                            events.disable_exit_key();
+                        }
+                    }
+                    Key::Char('d')
+                        if app.focus == Focus::Portfolio && app.selected_asset().is_some() =>
+                    {
+                        app.input_mode = util::app::InputMode::Delete;
+                        events.disable_exit_key();
+                    }
                     Key::Char('q') => {
                         break;
                     }
-                    Key::Left => {
-                        app.items.unselect();
+                    Key::Char('a') => {
+                        app.next_account();
                     }
-                    Key::Down => {
-                        if toggle {
-                            app.items.next();
-                        } else {
-                            app.table_portfolio.next();
-                        }
+                    Key::Char('c') => {
+                        app.toggle_chart();
                     }
-                    Key::Up => {
-                        if toggle {
-                            app.items.previous();
+                    //undo and redo the changes made in this session
+                    Key::Char('u') | Key::Ctrl('r') => {
+                        let result = if input == Key::Char('u') {
+                            app.undo()
                         } else {
-                            app.table_portfolio.previous();
+                            app.redo()
+                        };
+                        if let Err(error_msg) = result {
+                            app.error_msg = error_msg;
+                            app.input_mode = util::app::InputMode::ErrorDisplay;
+                            events.disable_exit_key();
                         }
                     }
-                    Key::Char(c) => {
-                        //let event_str = format!("{} pressed ({:x})", c, c as u8);
-                        //app.add_custom_event(event_str);
-                        // picks up the keycode for tab
-                        if c as u8 == 9 {
-                            toggle = !toggle;
-                        }
+                    Key::Down => match app.focus {
+                        Focus::Portfolio => app.table_portfolio.next(),
+                        Focus::Targets => app.table_targets.next(),
+                        Focus::History => app.table_history.next(),
+                        Focus::Schedule => app.table_schedule.next(),
+                        Focus::Backtest => app.table_backtest.next(),
+                        Focus::Projection => {}
+                    },
+                    Key::Up => match app.focus {
+                        Focus::Portfolio => app.table_portfolio.previous(),
+                        Focus::Targets => app.table_targets.previous(),
+                        Focus::History => app.table_history.previous(),
+                        Focus::Schedule => app.table_schedule.previous(),
+                        Focus::Backtest => app.table_backtest.previous(),
+                        Focus::Projection => {}
+                    },
+                    // collapse or expand the selected asset class
+                    Key::Char('\n') if app.focus == Focus::Targets => {
+                        app.toggle_target();
+                    }
+                    // mark the selected journal entry to compare the others with
+                    Key::Char('\n') if app.focus == Focus::History => {
+                        app.toggle_history_mark();
+                    }
+                    // picks up the keycode for tab, moves the arrow keys between the portfolio
+                    // and the target allocations
+                    Key::Char(c) if c as u8 == 9 => {
+                        app.toggle_focus();
                     }
                     Key::Backspace => {
-                        toggle = !toggle;
+                        app.toggle_focus();
                         app.add_custom_event("Backspace PRESSED".to_string());
                     }
                     _ => {}
                 },
                 util::app::InputMode::Editing => match input {
                     Key::Char('\n') => {
-                        //pull the new portfolio amount
+                        //pull the new portfolio amount or target percentage
                         let new_value: String = app.input.drain(..).collect();
 
-                        //validate the input to be a dollar amount
-                        if !input_validation.is_match(&new_value) {
-                            app.error_msg =
-                                "Input must be in the format of a dollar amount".to_string();
-                            app.input_mode = util::app::InputMode::ErrorDisplay;
-                        } else {
-                            if let Some(index) = app.table_portfolio.state.selected() {
-                                let row = &mut app.table_portfolio.items[index];
-                                row[1] = format!("${}", new_value.clone());
-                                //update the underlying asset
-                                app.update_asset(index, new_value);
+                        let updated = match app.focus {
+                            //the percentage is validated when the target is updated
+                            Focus::Targets => match app.table_targets.state.selected() {
+                                Some(index) => app.update_target(index, new_value),
+                                None => Ok(()),
+                            },
+                            //validate the input to be a dollar amount
+                            Focus::Portfolio if !input_validation.is_match(&new_value) => {
+                                Err("Input must be in the format of a dollar amount".to_string())
+                            }
+                            Focus::History
+                            | Focus::Schedule
+                            | Focus::Backtest
+                            | Focus::Projection => Ok(()),
+                            //update the underlying asset and the table row
+                            Focus::Portfolio => match app.table_portfolio.state.selected() {
+                                Some(index) => app.update_asset(index, new_value),
+                                None => Ok(()),
+                            },
+                        };
+                        match updated {
+                            Ok(()) => {
+                                app.input_mode = InputMode::Normal;
+                                events.enable_exit_key();
+                            }
+                            Err(error_msg) => {
+                                app.error_msg = error_msg;
+                                app.input_mode = util::app::InputMode::ErrorDisplay;
                             }
-                            app.input_mode = InputMode::Normal;
-                            events.enable_exit_key();
                         }
                     }
                     Key::Char(c) => {
@@ -129,20 +619,57 @@ fn main() -> Result<(), Box<dyn Error>> {
                             app.add_custom_event(
                                 format!("Execute Rebalance with {}", new_investment).to_string(),
                             );
-                            app.contribution_amount = new_investment.parse().unwrap();
-                            app.lazy_rebalance();
-                            //snapshot our portfolio to a csv file
-                            app.save_portfolio().expect("Error saving portfolio");
-                            //go back to normal mode after doing the rebalance
-                            app.input_mode = InputMode::Normal;
-                            events.enable_exit_key();
+                            app.contribution_amount = parse_decimal(&new_investment).unwrap();
+                            match app.rebalance() {
+                                //review the trades before anything is saved
+                                Ok(()) => app.input_mode = util::app::InputMode::Preview,
+                                Err(error_msg) => {
+                                    app.error_msg = error_msg;
+                                    app.input_mode = util::app::InputMode::ErrorDisplay;
+                                }
+                            }
                         }
                     }
-                    Key::Char(c) => {
-                        if c.is_numeric() || c == '.' {
-                            app.input.push(c);
+                    // tab cycles through the rebalance modes
+                    Key::Char('\t') => {
+                        app.rebalance_mode = app.rebalance_mode.next();
+                    }
+                    Key::Char(c) if (c.is_numeric() || c == '.') => {
+                        app.input.push(c);
+                    }
+                    Key::Backspace => {
+                        app.input.pop();
+                    }
+                    Key::Esc => {
+                        app.input_mode = InputMode::Normal;
+                        events.enable_exit_key();
+                    }
+                    _ => {}
+                },
+                util::app::InputMode::Withdraw => match input {
+                    Key::Char('\n') => {
+                        //get the amount of cash to raise
+                        let withdrawal: String = app.input.drain(..).collect();
+
+                        //validate the input to be a dollar amount
+                        if !input_validation.is_match(&withdrawal) {
+                            app.error_msg =
+                                "Input must be in the format of a dollar amount".to_string();
+                            app.input_mode = util::app::InputMode::ErrorDisplay;
+                        } else {
+                            app.add_custom_event(format!("Execute Withdrawal of {}", withdrawal));
+                            match app.withdraw(&parse_decimal(&withdrawal).unwrap()) {
+                                Ok(()) => app.input_mode = util::app::InputMode::Preview,
+                                Err(error_msg) => {
+                                    app.error_msg = error_msg;
+                                    app.input_mode = util::app::InputMode::ErrorDisplay;
+                                }
+                            }
                         }
                     }
+                    Key::Char(c) if (c.is_numeric() || c == '.') => {
+                        app.input.push(c);
+                    }
                     Key::Backspace => {
                         app.input.pop();
                     }
@@ -152,13 +679,124 @@ fn main() -> Result<(), Box<dyn Error>> {
                     }
                     _ => {}
                 },
-                util::app::InputMode::ErrorDisplay => match input {
+                util::app::InputMode::Schedule
+                | util::app::InputMode::Backtest
+                | util::app::InputMode::Projection => match input {
+                    Key::Char('\n') => {
+                        //the schedule, backtest or projection replaces the results until the
+                        //focus moves on
+                        let input = app.input.drain(..).collect::<String>();
+                        let planned = match app.input_mode {
+                            InputMode::Schedule => input
+                                .parse()
+                                .and_then(|schedule| app.plan_schedule(schedule)),
+                            InputMode::Backtest => input
+                                .parse()
+                                .and_then(|backtest| app.run_backtest(backtest)),
+                            _ => input.parse().and_then(|projection| app.project(projection)),
+                        };
+                        match planned {
+                            Ok(()) => {
+                                app.input_mode = InputMode::Normal;
+                                events.enable_exit_key();
+                            }
+                            Err(error_msg) => {
+                                app.error_msg = error_msg;
+                                app.input_mode = util::app::InputMode::ErrorDisplay;
+                            }
+                        }
+                    }
+                    Key::Char(c) => {
+                        app.input.push(c);
+                    }
+                    Key::Backspace => {
+                        app.input.pop();
+                    }
                     Key::Esc => {
+                        app.input.clear();
                         app.input_mode = InputMode::Normal;
                         events.enable_exit_key();
                     }
                     _ => {}
                 },
+                //'y' executes the previewed trades and saves them, Esc throws them away
+                util::app::InputMode::Preview => match input {
+                    Key::Char('y') => match app.execute_trades() {
+                        Ok(()) => {
+                            app.input_mode = InputMode::Normal;
+                            events.enable_exit_key();
+                        }
+                        Err(error_msg) => {
+                            app.error_msg = error_msg;
+                            app.input_mode = util::app::InputMode::ErrorDisplay;
+                        }
+                    },
+                    Key::Esc | Key::Char('n') => {
+                        app.discard_trades();
+                        app.input_mode = InputMode::Normal;
+                        events.enable_exit_key();
+                    }
+                    _ => {}
+                },
+                util::app::InputMode::Insert | util::app::InputMode::Rename => match input {
+                    Key::Char('\n') => {
+                        let new_value: String = app.input.drain(..).collect();
+
+                        let updated = if let InputMode::Insert = app.input_mode {
+                            app.insert_asset(new_value)
+                        } else {
+                            match app.table_portfolio.state.selected() {
+                                Some(index) => app.rename_asset(index, new_value),
+                                None => Ok(()),
+                            }
+                        };
+                        match updated {
+                            Ok(()) => {
+                                app.input_mode = InputMode::Normal;
+                                events.enable_exit_key();
+                            }
+                            Err(error_msg) => {
+                                app.error_msg = error_msg;
+                                app.input_mode = util::app::InputMode::ErrorDisplay;
+                            }
+                        }
+                    }
+                    Key::Char(c) => {
+                        app.input.push(c);
+                    }
+                    Key::Backspace => {
+                        app.input.pop();
+                    }
+                    Key::Esc => {
+                        app.input.clear();
+                        app.input_mode = InputMode::Normal;
+                        events.enable_exit_key();
+                    }
+                    _ => {}
+                },
+                //'y' removes the selected asset, any other key keeps it
+                util::app::InputMode::Delete => {
+                    let deleted = match (input, app.table_portfolio.state.selected()) {
+                        (Key::Char('y'), Some(index)) => app.delete_asset(index),
+                        _ => Ok(()),
+                    };
+                    match deleted {
+                        Ok(()) => {
+                            app.input_mode = InputMode::Normal;
+                            events.enable_exit_key();
+                        }
+                        Err(error_msg) => {
+                            app.error_msg = error_msg;
+                            app.input_mode = util::app::InputMode::ErrorDisplay;
+                        }
+                    }
+                }
+                util::app::InputMode::ErrorDisplay => {
+                    if input == Key::Esc {
+                        app.input_mode = InputMode::Normal;
+                        events.enable_exit_key();
+                    }
+                }
             },
             Event::Tick => {}
         }
@@ -166,3 +804,221 @@ fn main() -> Result<(), Box<dyn Error>> {
 
     Ok(())
 }
+
+/// Picks the files to load: those of the chosen profile, or of the "default" profile when there
+/// is one, with the file options given on the command line taking precedence
+fn portfolio_files(matches: &ArgMatches) -> Result<PortfolioFiles, String> {
+    let profile_errors = |errors: Vec<LoadError>| {
+        errors
+            .iter()
+            .map(|error| error.to_string())
+            .collect::<Vec<_>>()
+            .join("\n")
+    };
+
+    let mut files = match matches.value_of("profile") {
+        Some(name) => read_profile(name).map_err(profile_errors)?.ok_or_else(|| {
+            let path = profiles_path().map_or(String::new(), |path| {
+                format!(" in {}", path.to_string_lossy())
+            });
+            format!("No profile named '{}'{}", name, path)
+        })?,
+        None => read_profile("default")
+            .map_err(profile_errors)?
+            .unwrap_or_default(),
+    };
+
+    if let Some(targets) = matches.value_of("targets") {
+        files.targets = targets.to_string();
+    }
+    if let Some(portfolio) = matches.value_of("portfolio") {
+        files.portfolio = portfolio.to_string();
+    }
+    if let Some(accounts) = matches.value_of("accounts") {
+        files.accounts = Some(accounts.to_string());
+    }
+    if let Some(column) = matches.value_of("value-column") {
+        files.value_index = match column.parse::<usize>() {
+            // column 1 holds the asset name
+            Ok(column) if column >= 2 => column - 1,
+            _ => return Err("--value-column must be a column number of 2 or more".to_string()),
+        };
+    }
+
+    Ok(files)
+}
+
+/// Runs the rebalance without the TUI and prints the report, nothing is saved
+fn print_plan(app: &mut App, plan: &ArgMatches) -> Result<(), String> {
+    if !app.load_errors.is_empty() {
+        let errors: Vec<String> = app.load_errors.iter().map(|e| e.to_string()).collect();
+        return Err(errors.join("\n"));
+    }
+
+    let contribution = plan.value_of("contribute").unwrap();
+    app.contribution_amount = parse_decimal(contribution).ok_or_else(|| {
+        format!(
+            "--contribute must be a dollar amount, not '{}'",
+            contribution
+        )
+    })?;
+
+    match plan.value_of("account") {
+        Some(account) => app.select_account(account)?,
+        None if app.accounts.len() > 1 => {
+            return Err("Pick the account receiving the contribution with --account".to_string())
+        }
+        None => {}
+    }
+
+    let format: OutputFormat = plan.value_of("format").unwrap().parse()?;
+
+    app.rebalance()?;
+
+    let plan = app.plan.as_ref().unwrap();
+    let output = render(format, &app.portfolio, plan, app.unspent_cash.as_ref())
+        .map_err(|error| error.to_string())?;
+    print!("{}", output);
+
+    Ok(())
+}
+
+/// Plans a contribution schedule without the TUI and prints its purchases, nothing is saved
+fn print_schedule(app: &mut App, matches: &ArgMatches) -> Result<(), String> {
+    if !app.load_errors.is_empty() {
+        let errors: Vec<String> = app.load_errors.iter().map(|e| e.to_string()).collect();
+        return Err(errors.join("\n"));
+    }
+
+    let frequency: Frequency = matches.value_of("every").unwrap().parse()?;
+    let months = matches.value_of("months").unwrap();
+    let months = match months.parse::<u32>() {
+        Ok(months) if months > 0 => months,
+        _ => {
+            return Err(format!(
+                "--months must be a number of months, not '{}'",
+                months
+            ))
+        }
+    };
+    let amount_arg = |name: &str| {
+        matches
+            .value_of(name)
+            .map(|amount| {
+                parse_decimal(amount)
+                    .filter(|amount| *amount > BigRational::zero())
+                    .ok_or_else(|| format!("--{} must be a dollar amount, not '{}'", name, amount))
+            })
+            .transpose()
+    };
+
+    let schedule = match (amount_arg("amount")?, amount_arg("total")?) {
+        (Some(amount), _) => Schedule {
+            amount,
+            frequency,
+            months,
+        },
+        (None, Some(total)) => Schedule::spread(&total, frequency, months),
+        (None, None) => unreachable!("clap requires --amount or --total"),
+    };
+
+    app.plan_schedule(schedule)?;
+
+    let schedule = app.schedule.as_ref().unwrap();
+    let output = match matches.value_of("format").unwrap() {
+        "csv" => schedule_to_csv(app.schedule_periods()).map_err(|error| error.to_string())?,
+        _ => schedule_to_string(schedule, app.schedule_periods()),
+    };
+    print!("{}", output);
+
+    Ok(())
+}
+
+/// Replays a contribution over the price history without the TUI and prints how every strategy
+/// did, nothing is saved
+fn print_backtest(app: &mut App, matches: &ArgMatches) -> Result<(), String> {
+    if !app.load_errors.is_empty() {
+        let errors: Vec<String> = app.load_errors.iter().map(|e| e.to_string()).collect();
+        return Err(errors.join("\n"));
+    }
+    if app.prices.is_empty() {
+        return Err("--prices must name the price history of the assets".to_string());
+    }
+
+    let amount = matches.value_of("amount").unwrap();
+    let amount = parse_decimal(amount)
+        .filter(|amount| *amount >= BigRational::zero())
+        .ok_or_else(|| format!("--amount must be a dollar amount, not '{}'", amount))?;
+    let frequency: Frequency = matches.value_of("every").unwrap().parse()?;
+    let months = matches.value_of("rebalance-every").unwrap();
+    let calendar_months = match months.parse::<u32>() {
+        Ok(months) if months > 0 => months,
+        _ => {
+            return Err(format!(
+                "--rebalance-every must be a number of months, not '{}'",
+                months
+            ))
+        }
+    };
+
+    app.run_backtest(Backtest {
+        amount,
+        frequency,
+        calendar_months,
+    })?;
+
+    let backtest = app.backtest.as_ref().unwrap();
+    let output = match matches.value_of("format").unwrap() {
+        "csv" => backtest_to_csv(app.backtest_results()).map_err(|error| error.to_string())?,
+        _ => backtest_to_string(backtest, app.backtest_results()),
+    };
+    print!("{}", output);
+
+    Ok(())
+}
+
+/// Simulates the future value of the portfolio without the TUI and prints its yearly
+/// percentiles, nothing is saved
+fn print_projection(app: &mut App, matches: &ArgMatches) -> Result<(), String> {
+    if !app.load_errors.is_empty() {
+        let errors: Vec<String> = app.load_errors.iter().map(|e| e.to_string()).collect();
+        return Err(errors.join("\n"));
+    }
+
+    let amount_arg = |name: &str, minimum: BigRational| {
+        let amount = matches.value_of(name).unwrap();
+        parse_decimal(amount)
+            .filter(|amount| *amount >= minimum)
+            .ok_or_else(|| format!("--{} must be a dollar amount, not '{}'", name, amount))
+    };
+    let count_arg = |name: &str, unit: &str| {
+        let count = matches.value_of(name).unwrap();
+        match count.parse::<u32>() {
+            Ok(count) if count > 0 => Ok(count),
+            _ => Err(format!(
+                "--{} must be a number of {}, not '{}'",
+                name, unit, count
+            )),
+        }
+    };
+
+    let projection = Projection {
+        amount: amount_arg("amount", BigRational::zero())?,
+        frequency: matches.value_of("every").unwrap().parse()?,
+        years: count_arg("years", "years")?,
+        goal: amount_arg("goal", BigRational::new(1.into(), 100.into()))?,
+        rebalance_months: count_arg("rebalance-every", "months")?,
+    };
+
+    app.project(projection)?;
+
+    let projection = app.projection.as_ref().unwrap();
+    let result = app.projection_result().unwrap();
+    let output = match matches.value_of("format").unwrap() {
+        "csv" => projection_to_csv(result).map_err(|error| error.to_string())?,
+        _ => projection_to_string(projection, result),
+    };
+    print!("{}", output);
+
+    Ok(())
+}
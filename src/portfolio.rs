@@ -0,0 +1,484 @@
+use crate::config::PortfolioFiles;
+use crate::error::{read_rows, LoadError, Row};
+use crate::rebalance::{format_rational, Asset};
+use crate::store::{pad_like, read_lines, save_lines, to_line, CsvLine};
+use crate::targets::{create_target_tree, flatten_targets, TargetNode};
+use num::{BigRational, Zero};
+use std::{collections::HashMap, error::Error, path::Path};
+
+/// A brokerage or retirement account holding part of the household portfolio
+pub struct Account {
+    pub name: String,
+    pub path_to_portfolio: String,
+    /// only the assets listed in the account's portfolio file
+    pub holdings: Vec<Asset>,
+}
+
+/// A household portfolio as read from its files: the target allocation tree and the holdings
+/// of every account
+pub struct Portfolio {
+    pub target_tree: Vec<TargetNode>,
+    pub accounts: Vec<Account>,
+}
+
+impl Portfolio {
+    /// Reads the targets and either the single portfolio file or every account listed in the
+    /// accounts file. Reading carries on past a bad targets file so that every problem in every
+    /// file is reported at once
+    pub fn load(files: &PortfolioFiles) -> Result<Portfolio, Vec<LoadError>> {
+        let path_to_portfolio = files.portfolio.as_str();
+        let portfolio_value_index = files.value_index;
+
+        let mut errors = vec![];
+
+        let target_tree = create_target_tree(&files.targets).unwrap_or_else(|target_errors| {
+            errors.extend(target_errors);
+            vec![]
+        });
+        let target_map = flatten_targets(&target_tree);
+
+        let accounts = match &files.accounts {
+            Some(path_to_accounts) => {
+                create_accounts(path_to_accounts, portfolio_value_index, &target_map)
+            }
+            None => read_holdings(path_to_portfolio, portfolio_value_index, &target_map).map(
+                |holdings| {
+                    vec![Account {
+                        name: "Portfolio".to_string(),
+                        path_to_portfolio: path_to_portfolio.to_string(),
+                        holdings,
+                    }]
+                },
+            ),
+        };
+
+        match accounts {
+            Ok(accounts) if errors.is_empty() => Ok(Portfolio {
+                target_tree,
+                accounts,
+            }),
+            Ok(_) => Err(errors),
+            Err(account_errors) => {
+                errors.extend(account_errors);
+                Err(errors)
+            }
+        }
+    }
+
+    /// The effective target of every fund
+    pub fn targets(&self) -> HashMap<String, Target> {
+        flatten_targets(&self.target_tree)
+    }
+
+    /// Every asset of the household with the holdings of each account added up, ready to be
+    /// rebalanced
+    pub fn assets(&self) -> Vec<Asset> {
+        consolidate(&self.accounts, &self.targets())
+    }
+
+    /// Saves every account back to its portfolio file
+    pub fn save(&self, portfolio_value_index: usize) -> Result<(), Box<dyn Error>> {
+        for account in &self.accounts {
+            save_holdings(
+                &account.path_to_portfolio,
+                portfolio_value_index,
+                &account.holdings,
+                &[],
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Clone)]
+pub struct Percent(pub BigRational);
+
+/// A row of the targets file: the target allocation, the optional tolerance bands
+/// (absolute in percentage points, relative in percent of the target) and the optional
+/// minimum trade in dollars
+#[derive(Clone)]
+pub struct Target {
+    pub allocation: Percent,
+    pub absolute_band: Option<Percent>,
+    pub relative_band: Option<Percent>,
+    pub minimum_trade: Option<BigRational>,
+}
+
+/// Reads the effective target of every fund from a flat or nested targets file
+pub fn create_target_map(path_to_targets: &str) -> Result<HashMap<String, Target>, Vec<LoadError>> {
+    Ok(flatten_targets(&create_target_tree(path_to_targets)?))
+}
+
+/// Reads the accounts file, each row names an account and the portfolio file holding it.
+/// Relative portfolio paths are resolved against the accounts file's directory. The problems
+/// in every portfolio file are reported together
+pub fn create_accounts(
+    path_to_accounts: &str,
+    portfolio_value_index: usize,
+    target_map: &HashMap<String, Target>,
+) -> Result<Vec<Account>, Vec<LoadError>> {
+    let mut errors = vec![];
+    let rows = read_rows(path_to_accounts, &mut errors);
+
+    let base_dir = Path::new(path_to_accounts)
+        .parent()
+        .unwrap_or_else(|| Path::new(""));
+
+    let mut accounts = vec![];
+
+    for row in rows {
+        let (name, portfolio_file) = match (row.text(0), row.text(1)) {
+            (Ok(name), Ok(portfolio_file)) => (name.to_string(), portfolio_file),
+            (Err(error), _) | (_, Err(error)) => {
+                errors.push(error);
+                continue;
+            }
+        };
+        let path_to_portfolio = base_dir.join(portfolio_file).to_string_lossy().to_string();
+
+        match read_holdings(&path_to_portfolio, portfolio_value_index, target_map) {
+            Ok(holdings) => accounts.push(Account {
+                name,
+                path_to_portfolio,
+                holdings,
+            }),
+            Err(account_errors) => errors.extend(account_errors),
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(accounts)
+    } else {
+        Err(errors)
+    }
+}
+
+/// Reads the assets listed in a portfolio file that have a target, in file order. An asset may
+/// only be listed once per file
+pub fn read_holdings(
+    path_to_portfolio: &str,
+    portfolio_value_index: usize,
+    target_map: &HashMap<String, Target>,
+) -> Result<Vec<Asset>, Vec<LoadError>> {
+    let mut errors = vec![];
+    let rows = read_rows(path_to_portfolio, &mut errors);
+
+    let mut holdings = vec![];
+
+    for row in rows {
+        let (asset_name, value, shares, price) = match read_holding(&row, portfolio_value_index) {
+            Ok(holding) => holding,
+            Err(error) => {
+                errors.push(error);
+                continue;
+            }
+        };
+
+        // a second row would be merged away when the holdings are saved
+        if holdings
+            .iter()
+            .any(|asset: &Asset| asset.name == asset_name)
+        {
+            errors.push(row.bad_entry(format!("{} is listed more than once", asset_name)));
+            continue;
+        }
+
+        match target_map.get(&asset_name) {
+            None => {}
+            Some(target) => {
+                let mut asset = create_asset(asset_name, target, value);
+
+                if let (Some(shares), Some(price)) = (shares, price) {
+                    asset.set_holding(shares, price);
+                }
+
+                holdings.push(asset);
+            }
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(holdings)
+    } else {
+        Err(errors)
+    }
+}
+
+/// Reads the name, value and the optional shares and price columns that follow the value
+fn read_holding(
+    row: &Row,
+    portfolio_value_index: usize,
+) -> Result<
+    (
+        String,
+        BigRational,
+        Option<BigRational>,
+        Option<BigRational>,
+    ),
+    LoadError,
+> {
+    Ok((
+        row.text(0)?.to_string(),
+        row.decimal(portfolio_value_index)?,
+        row.optional_decimal(portfolio_value_index + 1)?,
+        row.optional_decimal(portfolio_value_index + 2)?,
+    ))
+}
+
+/// Adds up the holdings of every account into the household portfolio, with an empty asset
+/// for every target nobody holds yet. Held assets keep the order of the portfolio files and the
+/// empty ones follow sorted by name, so the same files always give the same order
+pub fn consolidate(accounts: &[Account], target_map: &HashMap<String, Target>) -> Vec<Asset> {
+    let mut portfolio: Vec<Asset> = vec![];
+
+    for holding in accounts.iter().flat_map(|account| account.holdings.iter()) {
+        match portfolio
+            .iter_mut()
+            .find(|asset| asset.name == holding.name)
+        {
+            Some(asset) => {
+                asset.value += &holding.value;
+                if let (Some(shares), Some(holding_shares)) = (&mut asset.shares, &holding.shares) {
+                    *shares += holding_shares;
+                }
+            }
+            None => portfolio.push(holding.clone()),
+        }
+    }
+
+    let mut unheld: Vec<(&String, &Target)> = target_map
+        .iter()
+        .filter(|(asset_name, _)| !portfolio.iter().any(|asset| &asset.name == *asset_name))
+        .collect();
+    unheld.sort_by(|a, b| a.0.cmp(b.0));
+
+    for (asset_name, target) in unheld {
+        portfolio.push(create_asset(
+            asset_name.clone(),
+            target,
+            BigRational::zero(),
+        ));
+    }
+
+    portfolio
+}
+
+pub fn create_portfolio(
+    path_to_portfolio: &str,
+    portfolio_value_index: usize,
+    target_map: &HashMap<String, Target>,
+) -> Result<Vec<Asset>, Vec<LoadError>> {
+    let account = Account {
+        name: "Portfolio".to_string(),
+        path_to_portfolio: path_to_portfolio.to_string(),
+        holdings: read_holdings(path_to_portfolio, portfolio_value_index, target_map)?,
+    };
+
+    Ok(consolidate(&[account], target_map))
+}
+
+/// Save one account's holdings back to its portfolio file. The value goes in the value column,
+/// followed by the shares and price when known. Other columns, the padding of each column,
+/// comments and blank lines are kept, and so are rows of assets without a target such as a
+/// cash row. Only the rows of the `removed` assets that are no longer held are dropped
+pub fn save_holdings(
+    path_to_portfolio: &str,
+    portfolio_value_index: usize,
+    holdings: &[Asset],
+    removed: &[String],
+) -> Result<(), Box<dyn Error>> {
+    let holding_row = |asset: &Asset, existing: Option<&csv::StringRecord>| {
+        let mut record: Vec<String> = existing
+            .map(|record| record.iter().map(str::to_string).collect())
+            .unwrap_or_else(|| vec![asset.name.to_string()]);
+
+        let mut set_column = |index: usize, text: String| {
+            if record.len() <= index {
+                record.resize(index + 1, String::new());
+            }
+            record[index] = text;
+        };
+
+        set_column(
+            portfolio_value_index,
+            format!("${}", format_rational(&asset.value, 2)),
+        );
+
+        if let (Some(shares), Some(price)) = (&asset.shares, &asset.price) {
+            set_column(portfolio_value_index + 1, format_rational(shares, 4));
+            set_column(
+                portfolio_value_index + 2,
+                format!("${}", format_rational(price, 2)),
+            );
+        }
+
+        match existing {
+            Some(existing) => to_line(&pad_like(existing, record)),
+            None => to_line(&record),
+        }
+    };
+
+    let mut lines = vec![];
+    let mut written: Vec<&str> = vec![];
+
+    for line in read_lines(path_to_portfolio)? {
+        match line {
+            CsvLine::Text(text) => lines.push(text),
+            CsvLine::Row(record) => {
+                let name = record.get(0).unwrap_or("").trim();
+                // a row repeating an asset already written is kept as it is, loading the file
+                // again reports it
+                match holdings.iter().find(|asset| asset.name == name) {
+                    Some(asset) if !written.contains(&name) => {
+                        lines.push(holding_row(asset, Some(&record))?);
+                        written.push(&asset.name);
+                    }
+                    None if removed.iter().any(|removed| removed == name) => {}
+                    _ => lines.push(to_line(
+                        &record.iter().map(str::to_string).collect::<Vec<_>>(),
+                    )?),
+                }
+            }
+        }
+    }
+
+    for asset in holdings {
+        if !written.contains(&asset.name.as_str()) {
+            lines.push(holding_row(asset, None)?);
+        }
+    }
+
+    save_lines(path_to_portfolio, &lines)
+}
+
+pub fn adjust_target_allocation_percent(target_allocation_percent: &BigRational) -> BigRational {
+    target_allocation_percent / BigRational::from_integer(100.into())
+}
+
+/// An asset with the target, bands and minimum trade of its targets file row
+pub fn create_asset(asset_name: String, target: &Target, value: BigRational) -> Asset {
+    let Percent(target_allocation_percent) = &target.allocation;
+    let target_allocation_percent = adjust_target_allocation_percent(target_allocation_percent);
+
+    let mut asset = Asset::new(asset_name, target_allocation_percent, value);
+    asset.set_bands(
+        target
+            .absolute_band
+            .as_ref()
+            .map(|Percent(band)| adjust_target_allocation_percent(band)),
+        target
+            .relative_band
+            .as_ref()
+            .map(|Percent(band)| adjust_target_allocation_percent(band)),
+    );
+    asset.minimum_trade = target.minimum_trade.clone();
+
+    asset
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rebalance::parse_decimal;
+    use std::{env, fs};
+
+    /// writes `contents` to a portfolio file in a directory of its own, saves leave backups
+    /// next to it
+    fn temp_file(directory: &str, contents: &str) -> String {
+        let directory = env::temp_dir().join(directory);
+        let _ = fs::remove_dir_all(&directory);
+        fs::create_dir_all(&directory).unwrap();
+
+        let path = directory
+            .join("portfolio.csv")
+            .to_string_lossy()
+            .to_string();
+        fs::write(&path, contents).unwrap();
+        path
+    }
+
+    fn remove_temp_file(path: &str) {
+        fs::remove_dir_all(Path::new(path).parent().unwrap()).unwrap();
+    }
+
+    fn target_map() -> HashMap<String, Target> {
+        let target = Target {
+            allocation: Percent(parse_decimal("100").unwrap()),
+            absolute_band: None,
+            relative_band: None,
+            minimum_trade: None,
+        };
+        vec![("Bond fund".to_string(), target)]
+            .into_iter()
+            .collect()
+    }
+
+    #[test]
+    fn an_asset_listed_twice_is_a_load_error() {
+        let path = temp_file(
+            "tui-rebalance-duplicate",
+            "Bond fund, $100\nCash, $5\nBond fund, $50\n",
+        );
+
+        let errors = read_holdings(&path, 1, &target_map()).err().unwrap();
+        remove_temp_file(&path);
+
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0]
+            .to_string()
+            .ends_with(":3: Bond fund is listed more than once"));
+    }
+
+    #[test]
+    fn the_shares_and_price_columns_set_the_value() {
+        let path = temp_file("tui-rebalance-shares", "Bond fund, $90.00, 4, 25\n");
+
+        let holdings = read_holdings(&path, 1, &target_map()).unwrap();
+        remove_temp_file(&path);
+
+        assert_eq!(holdings[0].value, parse_decimal("100").unwrap());
+        assert_eq!(holdings[0].shares, Some(parse_decimal("4").unwrap()));
+        assert_eq!(holdings[0].price, Some(parse_decimal("25").unwrap()));
+    }
+
+    #[test]
+    fn saving_keeps_the_rows_it_does_not_manage() {
+        let path = temp_file(
+            "tui-rebalance-unmanaged",
+            "# savings\nBond fund, $100.00\nCash, $5.00\nOld fund, $10.00\n",
+        );
+
+        let holdings = read_holdings(&path, 1, &target_map()).unwrap();
+        save_holdings(&path, 1, &holdings, &["Old fund".to_string()]).unwrap();
+        let saved = fs::read_to_string(&path).unwrap();
+        remove_temp_file(&path);
+
+        assert_eq!(saved, "# savings\nBond fund, $100.00\nCash, $5.00\n");
+    }
+
+    #[test]
+    fn targets_nobody_holds_follow_the_holdings_sorted_by_name() {
+        let target = target_map()["Bond fund"].clone();
+        let target_map: HashMap<String, Target> = ["Stock fund", "Bond fund", "Gold fund", "Cash"]
+            .iter()
+            .map(|name| (name.to_string(), target.clone()))
+            .collect();
+        let account = Account {
+            name: "Portfolio".to_string(),
+            path_to_portfolio: String::new(),
+            holdings: vec![create_asset(
+                "Stock fund".to_string(),
+                &target,
+                parse_decimal("100").unwrap(),
+            )],
+        };
+
+        let names: Vec<String> = consolidate(&[account], &target_map)
+            .into_iter()
+            .map(|asset| asset.name)
+            .collect();
+
+        assert_eq!(names, ["Stock fund", "Bond fund", "Cash", "Gold fund"]);
+    }
+}
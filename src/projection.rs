@@ -0,0 +1,602 @@
+use crate::error::{read_rows, LoadError};
+use crate::rebalance::{
+    band_deltas, deviation, format_f64, format_rational, full_deltas, level, parse_decimal, to_f64,
+    Asset, BandTarget, RebalanceMode,
+};
+use crate::schedule::Frequency;
+use chrono::NaiveDate;
+use num::{BigInt, BigRational, Zero};
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use rand_distr::StandardNormal;
+use serde::Serialize;
+use std::{fmt, io::Write, str::FromStr};
+use tabwriter::TabWriter;
+
+/// What an asset is expected to do, as read from the assumptions file
+pub struct Assumption {
+    pub name: String,
+    /// expected annual return as a fraction, e.g. 0.07 for 7%
+    pub expected_return: f64,
+    /// annual volatility (standard deviation of the return) as a fraction
+    pub volatility: f64,
+    /// correlation with the asset of each row of the assumptions file, in file order. Left out
+    /// correlations are taken from the other asset's row, or 0 when neither row has one
+    pub correlations: Vec<Option<f64>>,
+}
+
+/// Reads the assumptions file, each row is an asset, its expected annual return and its
+/// volatility in percent followed by its correlation with the asset of every row, e.g.
+/// `Domestic Stock ETF, 7, 16, 0.1, 0.2, 1`. Correlations may be left out, so the lower
+/// triangle of the matrix is enough
+pub fn read_assumptions(path: &str) -> Result<Vec<Assumption>, Vec<LoadError>> {
+    let mut errors = vec![];
+    let rows = read_rows(path, &mut errors);
+
+    let mut assumptions = vec![];
+    for row in rows {
+        let (name, expected_return, volatility) =
+            match (row.text(0), row.decimal(1), row.decimal(2)) {
+                (Ok(name), Ok(expected_return), Ok(volatility)) => {
+                    (name, expected_return, volatility)
+                }
+                (Err(error), _, _) | (_, Err(error), _) | (_, _, Err(error)) => {
+                    errors.push(error);
+                    continue;
+                }
+            };
+
+        let mut correlations = vec![];
+        for column in 3..row.columns() {
+            match row.optional_decimal(column) {
+                Ok(correlation) => correlations.push(correlation.as_ref().map(to_f64)),
+                Err(error) => errors.push(error),
+            }
+        }
+
+        assumptions.push(Assumption {
+            name: name.to_string(),
+            expected_return: to_f64(&expected_return) / 100.0,
+            volatility: to_f64(&volatility) / 100.0,
+            correlations,
+        });
+    }
+
+    if errors.is_empty() {
+        Ok(assumptions)
+    } else {
+        Err(errors)
+    }
+}
+
+/// The full correlation matrix of the assumptions, in file order. Fails when a correlation is
+/// outside -1 to 1 or the two rows of a pair disagree
+fn correlation_matrix(assumptions: &[Assumption]) -> Result<Vec<Vec<f64>>, String> {
+    let given =
+        |row: usize, column: usize| assumptions[row].correlations.get(column).copied().flatten();
+
+    let mut matrix = vec![vec![0.0; assumptions.len()]; assumptions.len()];
+    for (row, assumption) in assumptions.iter().enumerate() {
+        for (column, other) in assumptions.iter().enumerate() {
+            let correlation = match (given(row, column), given(column, row)) {
+                (Some(left), Some(right)) if (left - right).abs() > 1e-9 => {
+                    return Err(format!(
+                        "The correlation of {} and {} is given as both {} and {}",
+                        assumption.name, other.name, left, right
+                    ))
+                }
+                (Some(correlation), _) | (None, Some(correlation)) => correlation,
+                (None, None) if row == column => 1.0,
+                (None, None) => 0.0,
+            };
+
+            if !(-1.0..=1.0).contains(&correlation) || (row == column && correlation != 1.0) {
+                return Err(format!(
+                    "The correlation of {} and {} is {}, it must lie between -1 and 1 and be 1 \
+                     for an asset with itself",
+                    assumption.name, other.name, correlation
+                ));
+            }
+            matrix[row][column] = correlation;
+        }
+    }
+
+    Ok(matrix)
+}
+
+/// The lower triangular matrix `l` with `l * l^T == matrix`, used to turn independent draws into
+/// correlated ones. None when the matrix isn't positive semi-definite
+fn cholesky(matrix: &[Vec<f64>]) -> Option<Vec<Vec<f64>>> {
+    let size = matrix.len();
+    let mut lower = vec![vec![0.0; size]; size];
+
+    for row in 0..size {
+        for column in 0..=row {
+            let sum: f64 = (0..column).map(|k| lower[row][k] * lower[column][k]).sum();
+
+            if row == column {
+                let pivot = matrix[row][row] - sum;
+                if pivot < -1e-9 {
+                    return None;
+                }
+                lower[row][column] = pivot.max(0.0).sqrt();
+            } else if lower[column][column] > 0.0 {
+                lower[row][column] = (matrix[row][column] - sum) / lower[column][column];
+            }
+        }
+    }
+
+    Some(lower)
+}
+
+/// A recurring contribution projected over the years, e.g. $500 monthly for 20 years towards a
+/// goal of $1,000,000
+#[derive(Clone)]
+pub struct Projection {
+    /// cash contributed every period
+    pub amount: BigRational,
+    pub frequency: Frequency,
+    pub years: u32,
+    /// portfolio value whose chance of being reached by the end is reported
+    pub goal: BigRational,
+    /// months between the full rebalances made on top of rebalancing every contribution
+    pub rebalance_months: u32,
+}
+
+impl Projection {
+    /// The number of contributions, e.g. 240 for monthly over 20 years
+    pub fn periods(&self) -> u32 {
+        self.years * self.frequency.per_year()
+    }
+
+    /// Cash contributed over the whole projection
+    pub fn total(&self) -> BigRational {
+        &self.amount * BigRational::from_integer(BigInt::from(self.periods()))
+    }
+}
+
+impl fmt::Display for Projection {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "${} {} for {} years towards ${}",
+            format_rational(&self.amount, 2),
+            self.frequency,
+            self.years,
+            format_rational(&self.goal, 2)
+        )
+    }
+}
+
+/// Parses the projection typed into the TUI: the amount, the frequency, the number of years and
+/// the goal separated by commas, e.g. `500, monthly, 20, 1000000`. The portfolio is fully
+/// rebalanced every 12 months
+impl FromStr for Projection {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let columns: Vec<&str> = s.split(',').map(str::trim).collect();
+        let (amount, frequency, years, goal) = match columns[..] {
+            [amount, frequency, years, goal] => (amount, frequency, years, goal),
+            _ => {
+                return Err(
+                    "Enter the amount, frequency, years and goal separated by commas".to_string(),
+                )
+            }
+        };
+
+        let amount = parse_decimal(amount)
+            .filter(|amount| *amount >= BigRational::zero())
+            .ok_or_else(|| format!("{} is not a dollar amount", amount))?;
+        let frequency = frequency.parse()?;
+        let years = match years.parse::<u32>() {
+            Ok(years) if years > 0 => years,
+            _ => return Err(format!("{} is not a number of years", years)),
+        };
+        let goal = parse_decimal(goal)
+            .filter(|goal| *goal > BigRational::zero())
+            .ok_or_else(|| format!("{} is not a dollar amount", goal))?;
+
+        Ok(Projection {
+            amount,
+            frequency,
+            years,
+            goal,
+            rebalance_months: 12,
+        })
+    }
+}
+
+/// How many paths are simulated and the seed of the random numbers, the same seed always gives
+/// the same paths
+#[derive(Clone, Copy)]
+pub struct Simulation {
+    pub paths: usize,
+    pub seed: u64,
+}
+
+impl Default for Simulation {
+    fn default() -> Self {
+        Simulation {
+            paths: 1000,
+            seed: 1,
+        }
+    }
+}
+
+/// The spread of the simulated portfolio values
+pub struct ProjectionResult {
+    /// today followed by the date of every contribution
+    pub dates: Vec<NaiveDate>,
+    /// the 10th, 50th and 90th percentile of the portfolio value on each date
+    pub percentiles: Vec<[f64; 3]>,
+    /// share of the paths that end at or above the goal
+    pub goal_probability: f64,
+    pub simulation: Simulation,
+}
+
+/// Simulates `simulation.paths` futures of the portfolio `assets`. Every period each asset
+/// grows by a correlated lognormal return whose mean is its expected return, then the
+/// contribution is rebalanced into the portfolio with `mode`, replaced by a full rebalance every
+/// `rebalance_months`. Fractional shares are held and the engines run in floating point, exact
+/// rationals are far too slow for thousands of paths
+pub fn simulate(
+    assets: &[Asset],
+    assumptions: &[Assumption],
+    projection: &Projection,
+    simulation: Simulation,
+    mode: RebalanceMode,
+    band_target: BandTarget,
+    start: NaiveDate,
+) -> Result<ProjectionResult, String> {
+    if assets.is_empty() {
+        return Err("The portfolio has no assets to project".to_string());
+    }
+    if simulation.paths == 0 {
+        return Err("At least one path has to be simulated".to_string());
+    }
+
+    let rows = assets
+        .iter()
+        .map(|asset| {
+            assumptions
+                .iter()
+                .position(|assumption| assumption.name == asset.name)
+                .ok_or_else(|| format!("No expected return or volatility for {}", asset.name))
+        })
+        .collect::<Result<Vec<usize>, String>>()?;
+    if let Some(assumption) = assumptions
+        .iter()
+        .find(|assumption| assumption.volatility < 0.0 || assumption.expected_return <= -1.0)
+    {
+        return Err(format!(
+            "{} needs a volatility of 0 or more and an expected return above -100%",
+            assumption.name
+        ));
+    }
+
+    let correlations = correlation_matrix(assumptions)?;
+    let correlations: Vec<Vec<f64>> = rows
+        .iter()
+        .map(|row| {
+            rows.iter()
+                .map(|column| correlations[*row][*column])
+                .collect()
+        })
+        .collect();
+    let lower = cholesky(&correlations)
+        .ok_or_else(|| "The correlations don't form a valid correlation matrix".to_string())?;
+
+    // lognormal steps whose mean compounds to the expected annual return
+    let per_year = projection.frequency.per_year();
+    let step = 1.0 / f64::from(per_year);
+    let (drifts, shocks): (Vec<f64>, Vec<f64>) = rows
+        .iter()
+        .map(|row| {
+            let assumption = &assumptions[*row];
+            let volatility = assumption.volatility;
+            (
+                ((1.0 + assumption.expected_return).ln() - volatility * volatility / 2.0) * step,
+                volatility * step.sqrt(),
+            )
+        })
+        .unzip();
+    let rebalance_periods = (projection.rebalance_months * per_year / 12).max(1);
+
+    let targets: Vec<f64> = assets
+        .iter()
+        .map(|asset| to_f64(&asset.target_allocation_percent))
+        .collect();
+    let tolerances: Vec<Option<f64>> = assets
+        .iter()
+        .map(|asset| asset.tolerance().as_ref().map(to_f64))
+        .collect();
+    let amount = to_f64(&projection.amount);
+    let start_values: Vec<f64> = assets.iter().map(|asset| to_f64(&asset.value)).collect();
+    let periods = projection.periods() as usize;
+
+    let mut rng = ChaCha8Rng::seed_from_u64(simulation.seed);
+    // the value of every path on each date
+    let mut totals = vec![Vec::with_capacity(simulation.paths); periods + 1];
+
+    for _ in 0..simulation.paths {
+        let mut values = start_values.clone();
+        totals[0].push(values.iter().sum());
+
+        for (period, totals) in totals.iter_mut().enumerate().skip(1) {
+            let draws: Vec<f64> = (0..values.len())
+                .map(|_| rng.sample(StandardNormal))
+                .collect();
+            for (index, value) in values.iter_mut().enumerate() {
+                let shock: f64 = (0..=index)
+                    .map(|column| lower[index][column] * draws[column])
+                    .sum();
+                *value *= (drifts[index] + shocks[index] * shock).exp();
+            }
+
+            let mode = if period as u32 % rebalance_periods == 0 {
+                RebalanceMode::Full
+            } else {
+                mode
+            };
+            rebalance_values(
+                &mut values,
+                &targets,
+                &tolerances,
+                amount,
+                mode,
+                band_target,
+            );
+
+            totals.push(values.iter().sum());
+        }
+    }
+
+    let goal = to_f64(&projection.goal);
+    let reached = totals[periods]
+        .iter()
+        .filter(|total| **total >= goal)
+        .count();
+
+    Ok(ProjectionResult {
+        dates: (0..=periods as u32)
+            .map(|period| projection.frequency.advance(start, period))
+            .collect(),
+        percentiles: totals
+            .iter_mut()
+            .map(|totals| {
+                totals.sort_by(f64::total_cmp);
+                [
+                    percentile(totals, 10),
+                    percentile(totals, 50),
+                    percentile(totals, 90),
+                ]
+            })
+            .collect(),
+        goal_probability: reached as f64 / simulation.paths as f64,
+        simulation,
+    })
+}
+
+/// Adds `amount` to the asset `values` through the rebalance engine of `mode`, run in floating
+/// point. `targets` are the target allocations and `tolerances` the allowed drift of each asset
+/// as fractions
+fn rebalance_values(
+    values: &mut [f64],
+    targets: &[f64],
+    tolerances: &[Option<f64>],
+    amount: f64,
+    mode: RebalanceMode,
+    band_target: BandTarget,
+) {
+    let total = values.iter().sum::<f64>() + amount;
+    if total <= 0.0 {
+        return;
+    }
+
+    let deltas = match mode {
+        RebalanceMode::Full => full_deltas(&amount, values, targets),
+        RebalanceMode::Lazy => {
+            let target_values: Vec<f64> = targets.iter().map(|target| total * target).collect();
+            let deviations: Vec<f64> = values
+                .iter()
+                .zip(&target_values)
+                .map(|(value, target_value)| deviation(value, target_value))
+                .collect();
+            level(&amount, &target_values, &deviations)
+        }
+        RebalanceMode::Band => band_deltas(&amount, band_target, values, targets, tolerances),
+    };
+
+    for (value, delta) in values.iter_mut().zip(deltas) {
+        *value = (*value + delta).max(0.0);
+    }
+}
+
+/// The nearest rank percentile of sorted values
+fn percentile(sorted: &[f64], percent: usize) -> f64 {
+    let rank = (percent as f64 / 100.0 * (sorted.len() - 1) as f64).round() as usize;
+    sorted[rank]
+}
+
+/// The percentiles of one date as written by the csv export, in dollars
+#[derive(Serialize)]
+pub struct PercentileResult {
+    pub period: usize,
+    pub date: String,
+    pub p10: f64,
+    pub p50: f64,
+    pub p90: f64,
+}
+
+/// The cells of the projection table: a header row and a row per year, amounts to the cent
+pub fn to_projection_rows(projection: &Projection, result: &ProjectionResult) -> Vec<Vec<String>> {
+    let mut rows: Vec<Vec<String>> = vec![[
+        "Year",
+        "Date",
+        "10th percentile",
+        "Median",
+        "90th percentile",
+    ]
+    .iter()
+    .map(|header| header.to_string())
+    .collect()];
+
+    let per_year = projection.frequency.per_year() as usize;
+    for (period, (date, percentiles)) in result.dates.iter().zip(&result.percentiles).enumerate() {
+        if period % per_year != 0 {
+            continue;
+        }
+
+        let mut row = vec![(period / per_year).to_string(), date.to_string()];
+        row.extend(percentiles.iter().map(|value| format_f64(*value, 2)));
+        rows.push(row);
+    }
+
+    rows
+}
+
+/// The chance of reaching the goal and what went into the projection
+pub fn projection_summary(projection: &Projection, result: &ProjectionResult) -> Vec<String> {
+    let end = result
+        .dates
+        .last()
+        .map_or(String::new(), |date| date.to_string());
+
+    vec![
+        format!(
+            "Chance of reaching ${} by {}: {}%",
+            format_rational(&projection.goal, 2),
+            end,
+            format_f64(result.goal_probability * 100.0, 1)
+        ),
+        format!(
+            "Contributed: ${} over {} periods",
+            format_rational(&projection.total(), 2),
+            projection.periods()
+        ),
+        format!(
+            "{} paths from seed {}, fully rebalanced every {} months",
+            result.simulation.paths, result.simulation.seed, projection.rebalance_months
+        ),
+    ]
+}
+
+/// The projection as a tab aligned table of the yearly percentiles followed by its summary
+pub fn projection_to_string(projection: &Projection, result: &ProjectionResult) -> String {
+    let buf = to_projection_rows(projection, result)
+        .iter()
+        .map(|row| row.join("\t"))
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    let mut tw = TabWriter::new(vec![]);
+
+    tw.write_all(buf.as_bytes()).unwrap();
+    tw.flush().unwrap();
+
+    format!(
+        "{}\n\n{}\n",
+        String::from_utf8(tw.into_inner().unwrap()).unwrap(),
+        projection_summary(projection, result).join("\n")
+    )
+}
+
+/// The percentiles of every period in csv, one row per date
+pub fn projection_to_csv(result: &ProjectionResult) -> Result<String, Box<dyn std::error::Error>> {
+    let mut wtr = csv::Writer::from_writer(vec![]);
+    for (period, (date, percentiles)) in result.dates.iter().zip(&result.percentiles).enumerate() {
+        wtr.serialize(PercentileResult {
+            period,
+            date: date.to_string(),
+            p10: percentiles[0],
+            p50: percentiles[1],
+            p90: percentiles[2],
+        })?;
+    }
+
+    Ok(String::from_utf8(wtr.into_inner()?)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rebalance::{band_rebalance, full_rebalance, lazy_rebalance};
+
+    fn portfolio() -> Vec<Asset> {
+        let asset = |name: &str, target: &str, value: &str| {
+            let mut asset = Asset::new(
+                name.to_string(),
+                parse_decimal(target).unwrap(),
+                parse_decimal(value).unwrap(),
+            );
+            asset.set_bands(None, parse_decimal("0.25"));
+            asset
+        };
+
+        vec![
+            asset("Bond fund", "0.2", "16500"),
+            asset("TIPS fund", "0.1", "6500"),
+            asset("Domestic Stock ETF", "0.4", "43500"),
+            asset("International Stock ETF", "0.3", "33500"),
+        ]
+    }
+
+    /// the projection step has to land where the exact engine's plan does
+    fn assert_step_matches(mode: RebalanceMode, band_target: BandTarget, amount: &str) {
+        let assets = portfolio();
+        let amount = parse_decimal(amount).unwrap();
+        let plan = match mode {
+            RebalanceMode::Lazy => lazy_rebalance(&amount, &assets),
+            RebalanceMode::Full => full_rebalance(&amount, &assets),
+            RebalanceMode::Band => band_rebalance(&amount, band_target, &assets),
+        };
+
+        let mut values: Vec<f64> = assets.iter().map(|asset| to_f64(&asset.value)).collect();
+        let targets: Vec<f64> = assets
+            .iter()
+            .map(|asset| to_f64(&asset.target_allocation_percent))
+            .collect();
+        let tolerances: Vec<Option<f64>> = assets
+            .iter()
+            .map(|asset| asset.tolerance().as_ref().map(to_f64))
+            .collect();
+        rebalance_values(
+            &mut values,
+            &targets,
+            &tolerances,
+            to_f64(&amount),
+            mode,
+            band_target,
+        );
+
+        for ((value, asset), trade) in values.iter().zip(&assets).zip(&plan.assets) {
+            let exact = to_f64(&(&asset.value + &trade.delta));
+            assert!(
+                (value - exact).abs() < 1e-6,
+                "{} {}: {} instead of {}",
+                mode,
+                asset.name,
+                value,
+                exact
+            );
+        }
+    }
+
+    #[test]
+    fn a_lazy_step_matches_the_exact_engine() {
+        assert_step_matches(RebalanceMode::Lazy, BandTarget::Target, "500");
+        assert_step_matches(RebalanceMode::Lazy, BandTarget::Target, "20000");
+    }
+
+    #[test]
+    fn a_full_step_matches_the_exact_engine() {
+        assert_step_matches(RebalanceMode::Full, BandTarget::Target, "500");
+    }
+
+    #[test]
+    fn a_band_step_matches_the_exact_engine() {
+        assert_step_matches(RebalanceMode::Band, BandTarget::Target, "500");
+        assert_step_matches(RebalanceMode::Band, BandTarget::Edge, "500");
+    }
+}
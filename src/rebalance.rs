@@ -0,0 +1,1673 @@
+// rust imports
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fmt;
+use std::io::Write;
+use std::mem;
+use std::str::FromStr;
+
+// 3rd-party imports
+use num::{BigInt, BigRational};
+use num::{One, Zero};
+use num::{Signed, ToPrimitive};
+
+use tabwriter::TabWriter;
+
+#[derive(Clone)]
+pub struct Asset {
+    pub name: String,
+    pub value: BigRational,
+    pub target_allocation_percent: BigRational,
+    /// allowed drift in percentage points of the portfolio e.g. 0.05 for +/-5%
+    pub absolute_band: Option<BigRational>,
+    /// allowed drift relative to the target allocation e.g. 0.25 for +/-25% of target
+    pub relative_band: Option<BigRational>,
+    /// number of shares held, only known when the portfolio file carries a shares column
+    pub shares: Option<BigRational>,
+    /// price of a single share, only known when the portfolio file carries a price column
+    pub price: Option<BigRational>,
+    /// smallest trade worth placing, overrides the global minimum
+    pub minimum_trade: Option<BigRational>,
+}
+
+impl Asset {
+    pub fn new(name: String, target_percent: BigRational, value: BigRational) -> Self {
+        assert!(target_percent <= BigRational::one());
+        assert!(target_percent >= BigRational::zero());
+
+        Asset {
+            name,
+
+            value,
+            target_allocation_percent: target_percent,
+            absolute_band: None,
+            relative_band: None,
+            shares: None,
+            price: None,
+            minimum_trade: None,
+        }
+    }
+
+    /// Sets the holding from a share count and price, the value becomes shares * price
+    pub fn set_holding(&mut self, shares: BigRational, price: BigRational) {
+        self.value = &shares * &price;
+        self.shares = Some(shares);
+        self.price = Some(price);
+    }
+
+    /// Sets a new value, keeping the share count in line with the price when one is known
+    pub fn set_value(&mut self, value: BigRational) {
+        if let Some(ref price) = self.price {
+            if *price > BigRational::zero() {
+                self.shares = Some(&value / price);
+            }
+        }
+
+        self.value = value;
+    }
+
+    /// Sets the tolerance bands, both given as fractions in the same way as target_percent
+    pub fn set_bands(
+        &mut self,
+        absolute_band: Option<BigRational>,
+        relative_band: Option<BigRational>,
+    ) {
+        self.absolute_band = absolute_band;
+        self.relative_band = relative_band;
+    }
+
+    /// The allowed drift from the target allocation as a fraction of the portfolio.
+    /// When both bands are set the narrower one wins (the "5/25 rule")
+    pub fn tolerance(&self) -> Option<BigRational> {
+        let relative = self
+            .relative_band
+            .as_ref()
+            .map(|band| band * &self.target_allocation_percent);
+
+        match (self.absolute_band.clone(), relative) {
+            (Some(absolute), Some(relative)) => Some(absolute.min(relative)),
+            (absolute, relative) => absolute.or(relative),
+        }
+    }
+
+    /// True when the asset's share of `portfolio_total` has drifted outside its band
+    pub fn is_out_of_band(&self, portfolio_total: &BigRational) -> bool {
+        if *portfolio_total <= BigRational::zero() {
+            return false;
+        }
+
+        match self.tolerance() {
+            Some(tolerance) => {
+                let allocation = &self.value / portfolio_total;
+                (allocation - &self.target_allocation_percent).abs() > tolerance
+            }
+            None => false,
+        }
+    }
+}
+
+/// The trade a rebalance plans for one asset
+#[derive(Clone)]
+pub struct AssetPlan {
+    pub name: String,
+    /// the asset's share of the portfolio before the trades
+    pub actual_allocation: BigRational,
+    pub target_value: BigRational,
+    pub deviation: BigRational,
+    /// cash to buy with, negative for a sell
+    pub delta: BigRational,
+    /// whole shares to buy (or sell when negative) once the delta is rounded
+    pub shares_to_trade: Option<BigInt>,
+    /// a trade that was dropped for being under the minimum trade
+    pub dropped_delta: Option<BigRational>,
+}
+
+/// The trades a rebalance plans for a portfolio, the portfolio itself is left untouched
+#[derive(Clone)]
+pub struct RebalancePlan {
+    /// cash added to the portfolio, negative for a withdrawal
+    pub contribution: BigRational,
+    /// one plan per asset, in the order the assets were given
+    pub assets: Vec<AssetPlan>,
+}
+
+/// Selects how a rebalance spreads the contribution across the portfolio
+/// Lazy only buys (or sells on a withdrawal) the most out of balance assets
+/// Full buys and sells so every asset lands exactly on its target value
+/// Band only trades the assets that drifted outside their tolerance band
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RebalanceMode {
+    Lazy,
+    Full,
+    Band,
+}
+
+impl RebalanceMode {
+    /// Cycle to the next mode, used by the Exec prompt
+    pub fn next(self) -> RebalanceMode {
+        match self {
+            RebalanceMode::Lazy => RebalanceMode::Full,
+            RebalanceMode::Full => RebalanceMode::Band,
+            RebalanceMode::Band => RebalanceMode::Lazy,
+        }
+    }
+}
+
+impl fmt::Display for RebalanceMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RebalanceMode::Lazy => write!(f, "lazy"),
+            RebalanceMode::Full => write!(f, "full"),
+            RebalanceMode::Band => write!(f, "band"),
+        }
+    }
+}
+
+impl FromStr for RebalanceMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "lazy" => Ok(RebalanceMode::Lazy),
+            "full" => Ok(RebalanceMode::Full),
+            "band" => Ok(RebalanceMode::Band),
+            _ => Err(format!("unknown rebalance mode '{}'", s)),
+        }
+    }
+}
+
+/// Where a band rebalance moves an out of band asset to
+/// Edge trades the minimum needed to get back inside the band
+/// Target trades all the way back to the target allocation
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BandTarget {
+    Edge,
+    Target,
+}
+
+impl fmt::Display for BandTarget {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BandTarget::Edge => write!(f, "edge"),
+            BandTarget::Target => write!(f, "target"),
+        }
+    }
+}
+
+impl FromStr for BandTarget {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "edge" => Ok(BandTarget::Edge),
+            "target" => Ok(BandTarget::Target),
+            _ => Err(format!("unknown band target '{}'", s)),
+        }
+    }
+}
+
+pub fn portfolio_total(assets: &[Asset]) -> BigRational {
+    assets
+        .iter()
+        .fold(BigRational::zero(), |total, asset| total + &asset.value)
+}
+
+pub fn comparator(left: &AssetPlan, right: &AssetPlan) -> Ordering {
+    left.deviation.cmp(&right.deviation)
+}
+
+/// An asset while a rebalance works out its trade, `index` is its place in the caller's
+/// portfolio so the plan can be put back in that order once the trades are sorted around
+struct Trade<'a> {
+    asset: &'a Asset,
+    index: usize,
+    plan: AssetPlan,
+}
+
+/// Sets up the target value and deviation of every asset once `amount_to_contribute` is added,
+/// without any trades yet
+fn start_trades<'a>(amount_to_contribute: &BigRational, assets: &'a [Asset]) -> Vec<Trade<'a>> {
+    let portfolio_total = portfolio_total(assets);
+
+    let total: BigRational = &portfolio_total + amount_to_contribute;
+
+    assets
+        .iter()
+        .enumerate()
+        .map(|(index, asset)| {
+            let target_value = &total * &asset.target_allocation_percent;
+
+            let deviation = deviation(&asset.value, &target_value);
+
+            let actual_allocation = if portfolio_total <= BigRational::zero() {
+                BigRational::zero()
+            } else {
+                &asset.value / &portfolio_total
+            };
+
+            Trade {
+                asset,
+                index,
+                plan: AssetPlan {
+                    name: asset.name.clone(),
+                    actual_allocation,
+                    target_value,
+                    deviation,
+                    delta: BigRational::zero(),
+                    shares_to_trade: None,
+                    dropped_delta: None,
+                },
+            }
+        })
+        .collect()
+}
+
+/// Puts the trades back in the order of the caller's portfolio
+fn finish_plan(amount_to_contribute: &BigRational, mut trades: Vec<Trade>) -> RebalancePlan {
+    trades.sort_by_key(|trade| trade.index);
+
+    RebalancePlan {
+        contribution: amount_to_contribute.clone(),
+        assets: trades.into_iter().map(|trade| trade.plan).collect(),
+    }
+}
+
+/// The plan that takes back the trades of `plan` once they were made on `assets`, in the same
+/// order. Dropped trades were never made so they have nothing to take back
+pub fn reverse_plan(plan: &RebalancePlan, assets: &[Asset]) -> RebalancePlan {
+    let amount_to_contribute = -&plan.contribution;
+    let mut trades = start_trades(&amount_to_contribute, assets);
+
+    for (trade, made) in trades.iter_mut().zip(&plan.assets) {
+        trade.plan.delta = -&made.delta;
+        trade.plan.shares_to_trade = made.shares_to_trade.as_ref().map(|shares| -shares);
+    }
+
+    finish_plan(&amount_to_contribute, trades)
+}
+
+/// Plans the buys (or sells on a withdrawal) that bring the most out of balance assets closer
+/// to their targets, without trading the others
+pub fn lazy_rebalance(amount_to_contribute: &BigRational, assets: &[Asset]) -> RebalancePlan {
+    let mut trades = start_trades(amount_to_contribute, assets);
+
+    level_deviations(amount_to_contribute, &mut trades);
+
+    finish_plan(amount_to_contribute, trades)
+}
+
+/// Lazy rebalance that drops any trade smaller than the asset's minimum trade (or
+/// `minimum_trade` when the asset has none). The cash of a dropped trade is levelled across the
+/// remaining assets again, one drop at a time starting with the smallest trade, until every
+/// trade left clears its minimum. Dropped trades are kept in `dropped_delta` for reporting
+pub fn lazy_rebalance_with_minimum(
+    amount_to_contribute: &BigRational,
+    minimum_trade: Option<&BigRational>,
+    assets: &[Asset],
+) -> RebalancePlan {
+    let mut trades = start_trades(amount_to_contribute, assets);
+    level_deviations(amount_to_contribute, &mut trades);
+
+    loop {
+        let smallest_trade = trades
+            .iter()
+            .enumerate()
+            .filter(|(_, trade)| trade.plan.dropped_delta.is_none())
+            .filter_map(|(position, trade)| {
+                let delta = trade.plan.delta.abs();
+                let minimum = trade.asset.minimum_trade.as_ref().or(minimum_trade)?;
+
+                if delta > BigRational::zero() && delta < *minimum {
+                    Some((position, delta))
+                } else {
+                    None
+                }
+            })
+            .min_by(|(_, left), (_, right)| left.cmp(right));
+
+        let position = match smallest_trade {
+            Some((position, _)) => position,
+            None => break,
+        };
+
+        let plan = &mut trades[position].plan;
+        plan.dropped_delta = Some(mem::replace(&mut plan.delta, BigRational::zero()));
+
+        // level the contribution again over the assets that still trade, which sit in front
+        trades.sort_by_key(|trade| trade.plan.dropped_delta.is_some());
+        let trading = trades
+            .iter()
+            .take_while(|trade| trade.plan.dropped_delta.is_none())
+            .count();
+
+        for trade in trades[..trading].iter_mut() {
+            trade.plan.delta = BigRational::zero();
+        }
+
+        level_deviations(amount_to_contribute, &mut trades[..trading]);
+    }
+
+    finish_plan(amount_to_contribute, trades)
+}
+
+/// Lazy rebalance of the household `assets` that only trades the holdings of a single account.
+/// `account_values` maps each asset the account holds to its value in that account; the
+/// contribution stays within the account while the deviations are still measured against the
+/// household-wide targets. A withdrawal never sells more of an asset than the account holds.
+/// Trades under the minimum trade are dropped and levelled again as in
+/// `lazy_rebalance_with_minimum`
+pub fn lazy_account_rebalance(
+    amount_to_contribute: &BigRational,
+    minimum_trade: Option<&BigRational>,
+    account_values: &HashMap<String, BigRational>,
+    assets: &[Asset],
+) -> RebalancePlan {
+    account_rebalance(
+        amount_to_contribute,
+        false,
+        minimum_trade,
+        account_values,
+        assets,
+    )
+}
+
+/// Plans the sells that raise `amount_to_withdraw` in cash, selling the most overweight assets
+/// first and never selling more of an asset than `available_values` holds (every asset's value
+/// when there is a single account). Fails when the holdings are too small to cover the
+/// withdrawal
+pub fn withdraw(
+    amount_to_withdraw: &BigRational,
+    available_values: &HashMap<String, BigRational>,
+    assets: &[Asset],
+) -> Result<RebalancePlan, String> {
+    let available = available_values
+        .values()
+        .fold(BigRational::zero(), |total, value| total + value);
+
+    if *amount_to_withdraw > available {
+        return Err(format!(
+            "Cannot withdraw ${}, only ${} is available to sell",
+            format_rational(amount_to_withdraw, 2),
+            format_rational(&available, 2)
+        ));
+    }
+
+    // withdrawing everything leaves no target values to level against, every holding is sold
+    if portfolio_total(assets) == *amount_to_withdraw {
+        let amount_to_contribute = -amount_to_withdraw;
+        let trades = full_trades(&amount_to_contribute, assets);
+        return Ok(finish_plan(&amount_to_contribute, trades));
+    }
+
+    Ok(account_rebalance(
+        &-amount_to_withdraw,
+        false,
+        None,
+        available_values,
+        assets,
+    ))
+}
+
+/// Full rebalance of the household `assets` within a single account: the account's holdings
+/// are bought and sold until they all share the same deviation from their household target,
+/// which puts them on target when the account is large enough. Sells are capped at what the
+/// account holds
+pub fn full_account_rebalance(
+    amount_to_contribute: &BigRational,
+    account_values: &HashMap<String, BigRational>,
+    assets: &[Asset],
+) -> RebalancePlan {
+    account_rebalance(amount_to_contribute, true, None, account_values, assets)
+}
+
+fn account_rebalance(
+    amount_to_contribute: &BigRational,
+    full: bool,
+    minimum_trade: Option<&BigRational>,
+    account_values: &HashMap<String, BigRational>,
+    assets: &[Asset],
+) -> RebalancePlan {
+    // computes the household wide target values and deviations, trades are redone below
+    let mut trades = full_trades(amount_to_contribute, assets);
+
+    // assets the account can still trade freely, the rest either aren't held in the account,
+    // were capped at selling the whole account position or had their trade dropped
+    let mut capped: Vec<String> = vec![];
+
+    loop {
+        let is_tradable = |trade: &Trade| {
+            account_values.contains_key(&trade.asset.name)
+                && !capped.contains(&trade.asset.name)
+                && trade.plan.dropped_delta.is_none()
+        };
+
+        let mut amount_left_to_contribute = amount_to_contribute.clone();
+        for trade in trades.iter_mut() {
+            if capped.contains(&trade.asset.name) {
+                amount_left_to_contribute -= &trade.plan.delta;
+            } else if !account_values.contains_key(&trade.asset.name) {
+                trade.plan.delta = BigRational::zero();
+            }
+        }
+
+        trades.sort_by_key(|trade| !is_tradable(trade));
+        let tradable = trades.iter().take_while(|trade| is_tradable(trade)).count();
+
+        if full {
+            level_all_deviations(&amount_left_to_contribute, &mut trades[..tradable]);
+        } else {
+            for trade in trades[..tradable].iter_mut() {
+                trade.plan.delta = BigRational::zero();
+            }
+            level_deviations(&amount_left_to_contribute, &mut trades[..tradable]);
+        }
+
+        // cap the largest oversold position at the account's holding and level again
+        let oversold = trades[..tradable]
+            .iter_mut()
+            .filter(|trade| trade.plan.delta < -account_values[&trade.asset.name].clone())
+            .max_by(|left, right| {
+                let excess = |trade: &Trade| {
+                    -(trade.plan.delta.clone() + &account_values[&trade.asset.name])
+                };
+                excess(left).cmp(&excess(right))
+            });
+
+        if let Some(trade) = oversold {
+            trade.plan.delta = -account_values[&trade.asset.name].clone();
+            capped.push(trade.asset.name.clone());
+            continue;
+        }
+
+        // then drop the smallest trade under its minimum and level again
+        let smallest_trade = trades[..tradable]
+            .iter_mut()
+            .filter(|trade| {
+                let delta = trade.plan.delta.abs();
+                match trade.asset.minimum_trade.as_ref().or(minimum_trade) {
+                    Some(minimum) => delta > BigRational::zero() && delta < *minimum,
+                    None => false,
+                }
+            })
+            .min_by(|left, right| left.plan.delta.abs().cmp(&right.plan.delta.abs()));
+
+        match smallest_trade {
+            Some(trade) => {
+                let plan = &mut trade.plan;
+                plan.dropped_delta = Some(mem::replace(&mut plan.delta, BigRational::zero()));
+            }
+            None => break,
+        }
+    }
+
+    finish_plan(amount_to_contribute, trades)
+}
+
+/// Gives every asset the same deviation, buying and selling as needed, so that the trades add
+/// up to `amount_to_contribute`
+fn level_all_deviations(amount_to_contribute: &BigRational, trades: &mut [Trade]) {
+    let (total_target_value, total_deviation) = trades.iter().fold(
+        (BigRational::zero(), BigRational::zero()),
+        |(total_target_value, total_deviation), trade| {
+            let target_value = &trade.plan.target_value;
+            let deviation = &trade.plan.deviation;
+            (
+                total_target_value + target_value,
+                total_deviation + target_value * deviation,
+            )
+        },
+    );
+
+    if total_target_value <= BigRational::zero() {
+        return;
+    }
+
+    let level = (amount_to_contribute + total_deviation) / total_target_value;
+
+    for trade in trades.iter_mut() {
+        trade.plan.delta = &trade.plan.target_value * (&level - &trade.plan.deviation);
+    }
+}
+
+/// Hands `amount_to_contribute` to the assets with the lowest deviation first (highest on a
+/// withdrawal), raising them together until their deviations meet the next asset's
+fn level_deviations(amount_to_contribute: &BigRational, trades: &mut [Trade]) {
+    let target_values: Vec<BigRational> = trades
+        .iter()
+        .map(|trade| trade.plan.target_value.clone())
+        .collect();
+    let deviations: Vec<BigRational> = trades
+        .iter()
+        .map(|trade| trade.plan.deviation.clone())
+        .collect();
+
+    let deltas = level(amount_to_contribute, &target_values, &deviations);
+
+    for (trade, delta) in trades.iter_mut().zip(deltas) {
+        trade.plan.delta = delta;
+    }
+}
+
+/// A number the rebalance engines can work in: exact rationals for the plans, or f64 where speed
+/// matters more than the last cent, such as the thousands of paths of a projection
+pub trait Amount: Clone + PartialOrd + Signed {}
+
+impl<T: Clone + PartialOrd + Signed> Amount for T {}
+
+/// How far `value` is from `target_value`, equivalent to: (value - target_value) / target_value
+/// see: https://en.wikipedia.org/wiki/Approximation_error#Formal_Definition
+/// An asset without a target value has no deviation and is left out of the levelling
+pub fn deviation<T: Amount>(value: &T, target_value: &T) -> T {
+    if *target_value <= T::zero() {
+        return T::zero();
+    }
+
+    value.clone() / target_value.clone() - T::one()
+}
+
+/// The lazy rebalance trades of assets with the given target values and deviations, in the same
+/// order. The contribution goes to the assets with the lowest deviation first (highest on a
+/// withdrawal), raising them together until their deviations meet the next asset's. The last
+/// asset takes whatever is left, which only matters when some assets were left out of the
+/// levelling since the deviations otherwise all meet at zero
+pub fn level<T: Amount>(amount_to_contribute: &T, target_values: &[T], deviations: &[T]) -> Vec<T> {
+    let withdrawing = *amount_to_contribute < T::zero();
+
+    // assets without a target value take no part
+    let mut order: Vec<usize> = (0..target_values.len())
+        .filter(|index| target_values[*index] > T::zero())
+        .collect();
+    order.sort_by(|left, right| {
+        let result = deviations[*left]
+            .partial_cmp(&deviations[*right])
+            .unwrap_or(Ordering::Equal);
+
+        if withdrawing {
+            result.reverse()
+        } else {
+            result
+        }
+    });
+
+    let mut level = T::zero();
+    let mut levelled_target_value = T::zero();
+    let mut amount_left_to_contribute = amount_to_contribute.clone();
+    let mut levelled = 0;
+
+    for (position, index) in order.iter().enumerate() {
+        if amount_left_to_contribute.is_zero() {
+            break;
+        }
+
+        levelled = position + 1;
+        level = deviations[*index].clone();
+        levelled_target_value = levelled_target_value + target_values[*index].clone();
+
+        let next_level = match order.get(position + 1) {
+            Some(next) => deviations[*next].clone(),
+            None => {
+                level = level + amount_left_to_contribute / levelled_target_value;
+                break;
+            }
+        };
+
+        // cash needed to raise the levelled assets to the next asset's deviation
+        let cost = levelled_target_value.clone() * (next_level.clone() - level.clone());
+
+        if cost.abs() <= amount_left_to_contribute.abs() {
+            amount_left_to_contribute = amount_left_to_contribute - cost;
+            level = next_level;
+        } else {
+            level = level + amount_left_to_contribute / levelled_target_value;
+            break;
+        }
+    }
+
+    let mut deltas = vec![T::zero(); target_values.len()];
+    for index in &order[..levelled] {
+        deltas[*index] =
+            target_values[*index].clone() * (level.clone() - deviations[*index].clone());
+    }
+
+    deltas
+}
+
+/// The full rebalance trades that put every asset of `values` on its target allocation once
+/// `amount_to_contribute` is added
+pub fn full_deltas<T: Amount>(amount_to_contribute: &T, values: &[T], targets: &[T]) -> Vec<T> {
+    let total = sum(values) + amount_to_contribute.clone();
+
+    values
+        .iter()
+        .zip(targets)
+        .map(|(value, target)| total.clone() * target.clone() - value.clone())
+        .collect()
+}
+
+/// The band rebalance trades of assets with the given values, target allocations and tolerances,
+/// see `band_rebalance`
+pub fn band_deltas<T: Amount>(
+    amount_to_contribute: &T,
+    band_target: BandTarget,
+    values: &[T],
+    targets: &[T],
+    tolerances: &[Option<T>],
+) -> Vec<T> {
+    let total = sum(values) + amount_to_contribute.clone();
+
+    let mut amount_left_to_contribute = amount_to_contribute.clone();
+    let mut in_band_allocation = T::zero();
+    let mut out_of_band = vec![false; values.len()];
+
+    let mut deltas: Vec<T> = values
+        .iter()
+        .zip(targets)
+        .zip(tolerances)
+        .enumerate()
+        .map(|(index, ((value, target), tolerance))| {
+            let tolerance = match tolerance {
+                Some(tolerance)
+                    if total > T::zero()
+                        && (value.clone() / total.clone() - target.clone()).abs() > *tolerance =>
+                {
+                    tolerance.clone()
+                }
+                _ => {
+                    in_band_allocation = in_band_allocation.clone() + target.clone();
+                    return T::zero();
+                }
+            };
+
+            let new_allocation = match band_target {
+                BandTarget::Target => target.clone(),
+                BandTarget::Edge if *value < total.clone() * target.clone() => {
+                    target.clone() - tolerance
+                }
+                BandTarget::Edge => target.clone() + tolerance,
+            };
+
+            let delta = total.clone() * new_allocation - value.clone();
+            amount_left_to_contribute = amount_left_to_contribute.clone() - delta.clone();
+            out_of_band[index] = true;
+
+            delta
+        })
+        .collect();
+
+    // spread the leftover cash so the portfolio stays fully invested, falling back to every
+    // asset when they were all out of band
+    let spread_over_all = in_band_allocation <= T::zero();
+    let spread_allocation = if spread_over_all {
+        sum(targets)
+    } else {
+        in_band_allocation
+    };
+
+    if !amount_left_to_contribute.is_zero() && spread_allocation > T::zero() {
+        for (index, delta) in deltas.iter_mut().enumerate() {
+            if !spread_over_all && out_of_band[index] {
+                continue;
+            }
+
+            *delta = delta.clone()
+                + amount_left_to_contribute.clone() * targets[index].clone()
+                    / spread_allocation.clone();
+        }
+    }
+
+    deltas
+}
+
+fn sum<T: Amount>(values: &[T]) -> T {
+    values
+        .iter()
+        .fold(T::zero(), |total, value| total + value.clone())
+}
+
+/// Computes the buys and sells that put every asset exactly on its target value once
+/// `amount_to_contribute` is added (or withdrawn when negative)
+pub fn full_rebalance(amount_to_contribute: &BigRational, assets: &[Asset]) -> RebalancePlan {
+    finish_plan(
+        amount_to_contribute,
+        full_trades(amount_to_contribute, assets),
+    )
+}
+
+fn full_trades<'a>(amount_to_contribute: &BigRational, assets: &'a [Asset]) -> Vec<Trade<'a>> {
+    let mut trades = start_trades(amount_to_contribute, assets);
+
+    let values: Vec<BigRational> = assets.iter().map(|asset| asset.value.clone()).collect();
+    let targets = target_allocations(assets);
+    let deltas = full_deltas(amount_to_contribute, &values, &targets);
+
+    for (trade, delta) in trades.iter_mut().zip(deltas) {
+        trade.plan.delta = delta;
+    }
+
+    trades
+}
+
+fn target_allocations(assets: &[Asset]) -> Vec<BigRational> {
+    assets
+        .iter()
+        .map(|asset| asset.target_allocation_percent.clone())
+        .collect()
+}
+
+/// Only trades the assets whose allocation drifted outside their tolerance band, moving them to
+/// the band edge or back to target. Drift is measured against the portfolio total including
+/// `amount_to_contribute`, and whatever cash is left over is spread across the in band assets
+/// in proportion to their target allocation
+pub fn band_rebalance(
+    amount_to_contribute: &BigRational,
+    band_target: BandTarget,
+    assets: &[Asset],
+) -> RebalancePlan {
+    let mut trades = start_trades(amount_to_contribute, assets);
+
+    let values: Vec<BigRational> = assets.iter().map(|asset| asset.value.clone()).collect();
+    let tolerances: Vec<Option<BigRational>> = assets.iter().map(Asset::tolerance).collect();
+    let deltas = band_deltas(
+        amount_to_contribute,
+        band_target,
+        &values,
+        &target_allocations(assets),
+        &tolerances,
+    );
+
+    for (trade, delta) in trades.iter_mut().zip(deltas) {
+        trade.plan.delta = delta;
+    }
+
+    finish_plan(amount_to_contribute, trades)
+}
+
+/// Turns the delta of every asset with a known price into a whole number of shares, rounding
+/// towards zero. The leftover cash is then handed out one share at a time to the asset that is
+/// furthest below target (or raised from the asset furthest above target when the rounding left
+/// the trades short of a withdrawal) until no single share fits. Assets without a price keep
+/// their fractional delta. `assets` is the portfolio the plan was made for. Returns the cash
+/// left unspent, negative when a withdrawal could not be covered.
+pub fn round_to_whole_shares(assets: &[Asset], plan: &mut RebalancePlan) -> BigRational {
+    let mut unspent = plan.contribution.clone();
+
+    for (asset, trade) in assets.iter().zip(plan.assets.iter_mut()) {
+        let delta = match asset.price {
+            Some(ref price) if *price > BigRational::zero() => {
+                let shares_to_trade = (&trade.delta / price).trunc();
+                trade.shares_to_trade = Some(shares_to_trade.to_integer());
+                shares_to_trade * price
+            }
+            _ => trade.delta.clone(),
+        };
+
+        unspent -= &delta;
+        trade.delta = delta;
+    }
+
+    // how far off target an asset would be after its current trade, ties go to the asset that
+    // started out furthest from target so the result doesn't depend on the portfolio's order
+    let rank = |asset: &Asset, trade: &AssetPlan| {
+        let deviation_after_trade = if trade.target_value > BigRational::zero() {
+            (&asset.value + &trade.delta) / &trade.target_value - BigRational::one()
+        } else {
+            BigRational::zero()
+        };
+
+        (deviation_after_trade, trade.deviation.clone())
+    };
+
+    loop {
+        let buying = unspent >= BigRational::zero();
+
+        let candidates =
+            assets
+                .iter()
+                .zip(plan.assets.iter())
+                .enumerate()
+                .filter(|(_, (asset, trade))| {
+                    let price = match asset.price {
+                        Some(ref price) if *price > BigRational::zero() => price,
+                        _ => return false,
+                    };
+
+                    if buying {
+                        *price <= unspent
+                    } else {
+                        // never sell a position below zero
+                        &asset.value + &trade.delta >= *price
+                    }
+                });
+
+        let next = if buying {
+            candidates.min_by_key(|(_, (asset, trade))| rank(asset, trade))
+        } else {
+            candidates.max_by_key(|(_, (asset, trade))| rank(asset, trade))
+        };
+
+        let index = match next {
+            Some((index, _)) => index,
+            None => break,
+        };
+
+        let price = assets[index].price.clone().unwrap();
+        let (share, cash) = if buying {
+            (BigInt::one(), price)
+        } else {
+            (-BigInt::one(), -price)
+        };
+
+        unspent -= &cash;
+        let trade = &mut plan.assets[index];
+        trade.delta += cash;
+        trade.shares_to_trade = trade.shares_to_trade.take().map(|shares| shares + share);
+    }
+
+    unspent
+}
+
+/// How far a portfolio sits from its targets. Drifts are allocation minus target as fractions
+/// of the portfolio, so 0.05 is 5 percentage points
+pub struct DriftMetrics {
+    /// sum of the absolute drift of every asset
+    pub total_drift: BigRational,
+    /// largest absolute drift relative to the asset's target, e.g. 0.25 for 25% off target.
+    /// Assets without a target are left out
+    pub max_relative_deviation: BigRational,
+    /// root mean square of the drifts, the tracking error against the targets
+    pub rms_drift: f64,
+    /// smallest contribution that lets every asset reach its target without selling.
+    /// Holdings of assets without a target can't be fixed this way and are left out
+    pub cash_to_target: BigRational,
+}
+
+impl fmt::Display for DriftMetrics {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "total {} pts, max deviation {}%, RMS {} pts, cash to target ${}",
+            format_rational(&(&self.total_drift * hundred()), 2),
+            format_rational(&(&self.max_relative_deviation * hundred()), 1),
+            format_f64(self.rms_drift * 100.0, 2),
+            format_rational(&self.cash_to_target, 2),
+        )
+    }
+}
+
+/// The drift of the portfolio as it is now
+pub fn drift_metrics(assets: &[Asset]) -> DriftMetrics {
+    let values: Vec<BigRational> = assets.iter().map(|asset| asset.value.clone()).collect();
+    metrics_of(&values, assets)
+}
+
+/// The drift of the portfolio once the trades of `plan` are executed
+pub fn drift_metrics_after(assets: &[Asset], plan: &RebalancePlan) -> DriftMetrics {
+    let values: Vec<BigRational> = assets
+        .iter()
+        .zip(&plan.assets)
+        .map(|(asset, trade)| &asset.value + &trade.delta)
+        .collect();
+    metrics_of(&values, assets)
+}
+
+fn metrics_of(values: &[BigRational], assets: &[Asset]) -> DriftMetrics {
+    let total = values
+        .iter()
+        .fold(BigRational::zero(), |total, value| total + value);
+
+    let mut metrics = DriftMetrics {
+        total_drift: BigRational::zero(),
+        max_relative_deviation: BigRational::zero(),
+        rms_drift: 0.0,
+        cash_to_target: BigRational::zero(),
+    };
+    if total <= BigRational::zero() || assets.is_empty() {
+        return metrics;
+    }
+
+    let mut squares = BigRational::zero();
+    // the portfolio size at which no asset is above its target
+    let mut needed_total = total.clone();
+
+    for (value, asset) in values.iter().zip(assets) {
+        let target = &asset.target_allocation_percent;
+        let drift = (value / &total - target).abs();
+
+        if *target > BigRational::zero() {
+            let relative = &drift / target;
+            if relative > metrics.max_relative_deviation {
+                metrics.max_relative_deviation = relative;
+            }
+
+            let asset_total = value / target;
+            if asset_total > needed_total {
+                needed_total = asset_total;
+            }
+        }
+
+        squares += &drift * &drift;
+        metrics.total_drift += drift;
+    }
+
+    let mean_square = squares / BigRational::from_integer(BigInt::from(assets.len()));
+    metrics.rms_drift = to_f64(&mean_square).sqrt();
+    metrics.cash_to_target = needed_total - total;
+
+    metrics
+}
+
+pub fn to_f64(fraction: &BigRational) -> f64 {
+    let numerator = fraction.numer();
+    let denominator = fraction.denom();
+
+    numerator.to_f64().unwrap() / denominator.to_f64().unwrap()
+}
+
+// pub fn to_debug_string(balanced_portfolio: &Vec<Asset>) -> String {
+//     let mut buf = "ASSET NAME\tTARGET\tVALUE".to_string();
+
+//     for asset in balanced_portfolio {
+//         // let delta = match asset.delta {
+//         //     Some(ref delta) => delta.clone(),
+//         //     None => BigRational::zero(),
+//         // };
+
+//         let target_allocation_percent = to_f64(&asset.target_allocation_percent);
+
+//         let target_allocation_percent = if target_allocation_percent <= 1.0 {
+//             target_allocation_percent * 100.0
+//         } else {
+//             target_allocation_percent
+//         };
+
+//         // let actual_allocation = to_f64(&asset.actual_allocation);
+
+//         // let target_value = &(asset.target_value.clone()).unwrap();
+
+//         // let final_portion =
+//         //     (&asset.value + &delta) * &asset.target_allocation_percent / target_value;
+
+//         let line = format!(
+//             "{}\t{}%\t{}",
+//             asset.name,
+//             format_f64(target_allocation_percent, 3),
+//             format_f64(to_f64(&asset.value), 2)
+//         );
+
+//         buf = format!("{}\n{}", buf, line);
+//     }
+
+//     for asset in balanced_portfolio {
+//         // let delta = match asset.delta {
+//         //     Some(ref delta) => delta.clone(),
+//         //     None => BigRational::zero(),
+//         // };
+
+//         let target_allocation_percent = to_f64(&asset.target_allocation_percent);
+
+//         let target_allocation_percent = if target_allocation_percent <= 1.0 {
+//             target_allocation_percent * 100.0
+//         } else {
+//             target_allocation_percent
+//         };
+
+//         // let actual_allocation = to_f64(&asset.actual_allocation);
+
+//         // let target_value = &(asset.target_value.clone()).unwrap();
+
+//         // let final_portion =
+//         //     (&asset.value + &delta) * &asset.target_allocation_percent / target_value;
+
+//         let line = format!(
+//             "list.push(new Asset(1, `{}`, {}/100, {}));",
+//             asset.name,
+//             format_f64(target_allocation_percent, 3),
+//             format_f64(to_f64(&asset.value), 2)
+//         );
+
+//         buf = format!("{}\n{}", buf, line);
+//     }
+
+//     let mut tw = TabWriter::new(vec![]);
+
+//     tw.write_all(buf.as_bytes()).unwrap();
+//     tw.flush().unwrap();
+
+//     String::from_utf8(tw.into_inner().unwrap()).unwrap()
+// }
+
+/// Function to create the vector representation for display the balanced portfolio
+/// in a tui stateful table
+pub fn to_vec_display(assets: &[Asset], plan: &RebalancePlan) -> Vec<Vec<String>> {
+    let mut display = Vec::<Vec<String>>::new();
+
+    let deltas: Vec<BigRational> = plan
+        .assets
+        .iter()
+        .map(|trade| trade.delta.clone())
+        .collect();
+    let target_values: Vec<BigRational> = plan
+        .assets
+        .iter()
+        .map(|trade| trade.target_value.clone())
+        .collect();
+
+    let rounded_deltas = round_preserving_total(&deltas, 2);
+    let rounded_target_values = round_preserving_total(&target_values, 2);
+
+    for (index, (asset, trade)) in assets.iter().zip(&plan.assets).enumerate() {
+        let actual_allocation = &trade.actual_allocation * hundred();
+        let final_portion = final_portion(asset, &deltas[index], &target_values[index]);
+
+        display.push(vec![
+            asset.name.clone().to_string(),
+            format_rational(&actual_allocation, 3),
+            format_rational(&final_portion, 3),
+            format_rational(&rounded_target_values[index], 2),
+            format_delta(&rounded_deltas[index], &trade.dropped_delta),
+            format_shares(&trade.shares_to_trade),
+        ]);
+    }
+
+    display
+}
+
+fn hundred() -> BigRational {
+    BigRational::from_integer(BigInt::from(100))
+}
+
+/// The asset's share of the portfolio after the trade, in percent
+fn final_portion(asset: &Asset, delta: &BigRational, target_value: &BigRational) -> BigRational {
+    if target_value.is_zero() {
+        return BigRational::zero();
+    }
+
+    (&asset.value + delta) * &asset.target_allocation_percent / target_value * hundred()
+}
+
+/// Shows a dropped trade in place of the zero delta so it doesn't silently vanish
+fn format_delta(delta: &BigRational, dropped_delta: &Option<BigRational>) -> String {
+    match dropped_delta {
+        Some(dropped_delta) => format!("dropped {}", format_rational(dropped_delta, 2)),
+        None => format_rational(delta, 2),
+    }
+}
+
+fn format_shares(shares_to_trade: &Option<BigInt>) -> String {
+    match shares_to_trade {
+        Some(shares) => shares.to_string(),
+        None => String::new(),
+    }
+}
+
+/// The cells of the rebalance report: a header row, a row per asset and a row of totals
+pub fn to_report_rows(assets: &[Asset], plan: &RebalancePlan) -> Vec<Vec<String>> {
+    let mut rows: Vec<Vec<String>> = vec![[
+        "Asset name",
+        "Asset value",
+        "Holdings %",
+        "New holdings %",
+        "Target allocation %",
+        "Target value",
+        "$ to buy/sell",
+        "Shares to buy/sell",
+    ]
+    .iter()
+    .map(|header| header.to_string())
+    .collect()];
+
+    let mut asset_values = vec![];
+    let mut current_holdings = vec![];
+    let mut new_holdings = vec![];
+    let mut target_allocations = vec![];
+    let mut target_values = vec![];
+    let mut deltas = vec![];
+
+    for (asset, trade) in assets.iter().zip(&plan.assets) {
+        let delta = trade.delta.clone();
+        let target_value = trade.target_value.clone();
+
+        let target_allocation_percent = if asset.target_allocation_percent <= BigRational::one() {
+            &asset.target_allocation_percent * hundred()
+        } else {
+            asset.target_allocation_percent.clone()
+        };
+
+        asset_values.push(asset.value.clone());
+        current_holdings.push(&trade.actual_allocation * hundred());
+        new_holdings.push(final_portion(asset, &delta, &target_value));
+        target_allocations.push(target_allocation_percent);
+        target_values.push(target_value);
+        deltas.push(delta);
+    }
+
+    // every column is rounded so that its rows add up to the rounded total
+    let columns: Vec<(Vec<BigRational>, usize)> = vec![
+        (round_preserving_total(&asset_values, 2), 2),
+        (round_preserving_total(&current_holdings, 3), 3),
+        (round_preserving_total(&new_holdings, 3), 3),
+        (round_preserving_total(&target_allocations, 3), 3),
+        (round_preserving_total(&target_values, 2), 2),
+        (round_preserving_total(&deltas, 2), 2),
+    ];
+
+    for (index, (asset, trade)) in assets.iter().zip(&plan.assets).enumerate() {
+        rows.push(vec![
+            asset.name.clone(),
+            format_rational(&columns[0].0[index], 2),
+            format_rational(&columns[1].0[index], 3),
+            format_rational(&columns[2].0[index], 3),
+            format_rational(&columns[3].0[index], 3),
+            format_rational(&columns[4].0[index], 2),
+            format_delta(&columns[5].0[index], &trade.dropped_delta),
+            format_shares(&trade.shares_to_trade),
+        ]);
+    }
+
+    // totals
+    let mut totals = vec!["Total".to_string()];
+    totals.extend(columns.iter().map(|(column, dec_places)| {
+        let total = column
+            .iter()
+            .fold(BigRational::zero(), |total, value| total + value);
+        format_rational(&total, *dec_places)
+    }));
+    // shares of different assets don't add up
+    totals.push(String::new());
+    rows.push(totals);
+
+    rows
+}
+
+/// The rebalance report as a tab aligned table
+pub fn to_string(assets: &[Asset], plan: &RebalancePlan) -> String {
+    let buf = to_report_rows(assets, plan)
+        .iter()
+        .map(|row| row.join("\t"))
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    let mut tw = TabWriter::new(vec![]);
+
+    tw.write_all(buf.as_bytes()).unwrap();
+    tw.flush().unwrap();
+
+    String::from_utf8(tw.into_inner().unwrap()).unwrap()
+}
+
+/// Parses a decimal string such as `16500.10`, `-25` or `$1,000.50` into an exact rational.
+/// Returns `None` for anything that isn't a plain decimal number
+pub fn parse_decimal(input: &str) -> Option<BigRational> {
+    let input = input.trim();
+    let (negative, input) = match input.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, input.strip_prefix('+').unwrap_or(input)),
+    };
+    let input = input.strip_prefix('$').unwrap_or(input).replace(',', "");
+
+    let (whole, fraction) = match input.find('.') {
+        Some(index) => (&input[..index], &input[index + 1..]),
+        None => (&input[..], ""),
+    };
+
+    let is_digits = |part: &str| part.chars().all(|c| c.is_ascii_digit());
+    if (whole.is_empty() && fraction.is_empty()) || !is_digits(whole) || !is_digits(fraction) {
+        return None;
+    }
+
+    let numerator: BigInt = format!("0{}{}", whole, fraction).parse().ok()?;
+    let denominator = num::pow(BigInt::from(10), fraction.len());
+    let value = BigRational::new(numerator, denominator);
+
+    Some(if negative { -value } else { value })
+}
+
+/// Formats a rational with `dec_places` decimals, rounding half away from zero
+pub fn format_rational(value: &BigRational, dec_places: usize) -> String {
+    let scale = num::pow(BigInt::from(10), dec_places);
+    let scaled = (value * BigRational::from_integer(scale.clone()))
+        .round()
+        .to_integer();
+
+    let sign = if scaled.is_negative() { "-" } else { "" };
+    let scaled = scaled.abs();
+    let whole = &scaled / &scale;
+
+    if dec_places == 0 {
+        return format!("{}{}", sign, whole);
+    }
+
+    let fraction = (&scaled % &scale).to_string();
+    format!(
+        "{}{}.{}{}",
+        sign,
+        whole,
+        "0".repeat(dec_places - fraction.len()),
+        fraction
+    )
+}
+
+/// Formats a rational with up to `max_places` decimals, leaving out trailing zeros so that
+/// e.g. 20 is written as `20` and 12.5 as `12.5`
+pub fn format_trimmed(value: &BigRational, max_places: usize) -> String {
+    let formatted = format_rational(value, max_places);
+
+    if formatted.contains('.') {
+        formatted
+            .trim_end_matches('0')
+            .trim_end_matches('.')
+            .to_string()
+    } else {
+        formatted
+    }
+}
+
+/// Rounds every value to `dec_places` decimals so that the rounded values add up to the
+/// rounded total, handing the leftover units to the largest remainders
+pub fn round_preserving_total(values: &[BigRational], dec_places: usize) -> Vec<BigRational> {
+    let scale = BigRational::from_integer(num::pow(BigInt::from(10), dec_places));
+
+    let scaled: Vec<BigRational> = values.iter().map(|value| value * &scale).collect();
+    let mut rounded: Vec<BigRational> = scaled.iter().map(|value| value.floor()).collect();
+
+    let total = scaled
+        .iter()
+        .fold(BigRational::zero(), |total, value| total + value)
+        .round();
+    let floored = rounded
+        .iter()
+        .fold(BigRational::zero(), |total, value| total + value);
+    let leftover = (total - floored).to_integer().to_usize().unwrap_or(0);
+
+    let mut by_remainder: Vec<usize> = (0..values.len()).collect();
+    by_remainder.sort_by(|&left, &right| {
+        let remainder = |index: usize| &scaled[index] - &rounded[index];
+        remainder(right).cmp(&remainder(left))
+    });
+
+    for &index in by_remainder.iter().take(leftover) {
+        rounded[index] += BigRational::one();
+    }
+
+    rounded.iter().map(|value| value / &scale).collect()
+}
+
+pub fn format_f64(price: f64, dec_places: usize) -> String {
+    format!("{:.*}", dec_places, price)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn amount(input: &str) -> BigRational {
+        parse_decimal(input).unwrap()
+    }
+
+    fn asset(name: &str, target_percent: &str, value: &str) -> Asset {
+        Asset::new(name.to_string(), amount(target_percent), amount(value))
+    }
+
+    fn priced_asset(name: &str, target_percent: &str, shares: &str, price: &str) -> Asset {
+        let mut asset = asset(name, target_percent, "0");
+        asset.set_holding(amount(shares), amount(price));
+        asset
+    }
+
+    fn holdings(assets: &[Asset]) -> HashMap<String, BigRational> {
+        assets
+            .iter()
+            .map(|asset| (asset.name.clone(), asset.value.clone()))
+            .collect()
+    }
+
+    fn deltas(plan: &RebalancePlan) -> Vec<BigRational> {
+        plan.assets
+            .iter()
+            .map(|trade| trade.delta.clone())
+            .collect()
+    }
+
+    #[test]
+    fn withdrawing_everything_sells_every_holding() {
+        let assets = vec![asset("Bonds", "0.4", "300"), asset("Stocks", "0.6", "700")];
+
+        let plan = withdraw(&amount("1000"), &holdings(&assets), &assets).unwrap();
+
+        assert_eq!(deltas(&plan), vec![amount("-300"), amount("-700")]);
+        assert_eq!(plan.contribution, amount("-1000"));
+    }
+
+    #[test]
+    fn a_reversed_plan_takes_the_trades_back() {
+        let assets = vec![asset("Bonds", "0.4", "300"), asset("Stocks", "0.6", "700")];
+        let plan = lazy_rebalance(&amount("500"), &assets);
+
+        let traded: Vec<Asset> = assets
+            .iter()
+            .zip(&plan.assets)
+            .map(|(asset, trade)| {
+                let mut asset = asset.clone();
+                asset.set_value(&asset.value + &trade.delta);
+                asset
+            })
+            .collect();
+        let reversed = reverse_plan(&plan, &traded);
+
+        assert_eq!(reversed.contribution, amount("-500"));
+        assert_eq!(deltas(&reversed), vec![amount("-300"), amount("-200")]);
+        assert_eq!(reversed.assets[0].actual_allocation, amount("0.4"));
+        assert_eq!(reversed.assets[0].target_value, amount("400"));
+    }
+
+    #[test]
+    fn levelling_a_subset_hands_the_rest_to_the_last_asset() {
+        let assets = vec![
+            asset("Bonds", "0.4", "100"),
+            asset("TIPS", "0.4", "200"),
+            asset("Stocks", "0.2", "700"),
+        ];
+        let contribution = amount("2000");
+
+        // only the first two assets are levelled, once they meet the remaining cash is split
+        // between them even though it takes them past the third
+        let mut trades = start_trades(&contribution, &assets);
+        trades.truncate(2);
+        level_deviations(&contribution, &mut trades);
+        trades.sort_by_key(|trade| trade.index);
+
+        let levelled: Vec<BigRational> = trades
+            .iter()
+            .map(|trade| trade.plan.delta.clone())
+            .collect();
+        assert_eq!(levelled, vec![amount("1050"), amount("950")]);
+    }
+
+    #[test]
+    fn lazy_rebalance_levels_every_asset_to_the_same_deviation() {
+        let assets = vec![asset("Bonds", "0.5", "400"), asset("Stocks", "0.5", "500")];
+
+        let plan = lazy_rebalance(&amount("200"), &assets);
+
+        assert_eq!(deltas(&plan), vec![amount("150"), amount("50")]);
+    }
+
+    fn banded_asset(name: &str, target_percent: &str, value: &str, absolute: &str) -> Asset {
+        let mut asset = asset(name, target_percent, value);
+        asset.set_bands(Some(amount(absolute)), None);
+        asset
+    }
+
+    #[test]
+    fn the_narrower_band_wins() {
+        let mut large = asset("Stocks", "0.4", "0");
+        large.set_bands(Some(amount("0.05")), Some(amount("0.25")));
+        let mut small = asset("Gold", "0.1", "0");
+        small.set_bands(Some(amount("0.05")), Some(amount("0.25")));
+
+        assert_eq!(large.tolerance(), Some(amount("0.05")));
+        assert_eq!(small.tolerance(), Some(amount("0.025")));
+        assert_eq!(asset("Bonds", "0.5", "0").tolerance(), None);
+    }
+
+    #[test]
+    fn an_asset_is_out_of_band_once_it_drifts_past_its_tolerance() {
+        let total = amount("1000");
+
+        assert!(banded_asset("Stocks", "0.4", "460", "0.05").is_out_of_band(&total));
+        assert!(banded_asset("Stocks", "0.4", "340", "0.05").is_out_of_band(&total));
+        assert!(!banded_asset("Stocks", "0.4", "450", "0.05").is_out_of_band(&total));
+        assert!(!asset("Stocks", "0.4", "900").is_out_of_band(&total));
+    }
+
+    #[test]
+    fn band_rebalance_moves_out_of_band_assets_to_the_target_or_the_edge() {
+        let assets = vec![
+            banded_asset("Stocks", "0.6", "700", "0.05"),
+            banded_asset("Bonds", "0.4", "300", "0.05"),
+        ];
+
+        let to_target = band_rebalance(&BigRational::zero(), BandTarget::Target, &assets);
+        let to_edge = band_rebalance(&BigRational::zero(), BandTarget::Edge, &assets);
+
+        assert_eq!(deltas(&to_target), vec![amount("-100"), amount("100")]);
+        assert_eq!(deltas(&to_edge), vec![amount("-50"), amount("50")]);
+    }
+
+    #[test]
+    fn band_rebalance_spreads_the_cash_left_over_across_the_in_band_assets() {
+        let assets = vec![
+            banded_asset("Stocks", "0.5", "600", "0.05"),
+            banded_asset("Bonds", "0.25", "200", "0.05"),
+            banded_asset("TIPS", "0.25", "200", "0.05"),
+        ];
+
+        let plan = band_rebalance(&BigRational::zero(), BandTarget::Target, &assets);
+
+        assert_eq!(
+            deltas(&plan),
+            vec![amount("-100"), amount("50"), amount("50")]
+        );
+    }
+
+    #[test]
+    fn trades_under_the_minimum_are_dropped_and_levelled_again() {
+        let assets = vec![asset("Bonds", "0.5", "400"), asset("Stocks", "0.5", "500")];
+
+        let plan = lazy_rebalance_with_minimum(&amount("200"), Some(&amount("60")), &assets);
+
+        assert_eq!(deltas(&plan), vec![amount("200"), amount("0")]);
+        assert_eq!(plan.assets[0].dropped_delta, None);
+        assert_eq!(plan.assets[1].dropped_delta, Some(amount("50")));
+    }
+
+    #[test]
+    fn an_asset_minimum_overrides_the_global_one() {
+        let mut assets = vec![asset("Bonds", "0.5", "400"), asset("Stocks", "0.5", "500")];
+        assets[1].minimum_trade = Some(amount("10"));
+
+        let plan = lazy_rebalance_with_minimum(&amount("200"), Some(&amount("60")), &assets);
+
+        assert_eq!(deltas(&plan), vec![amount("150"), amount("50")]);
+        assert!(plan
+            .assets
+            .iter()
+            .all(|trade| trade.dropped_delta.is_none()));
+    }
+
+    #[test]
+    fn whole_shares_spend_the_leftover_on_the_cheapest_share_that_fits() {
+        let assets = vec![
+            priced_asset("Bonds", "0.5", "10", "10"),
+            priced_asset("Stocks", "0.5", "100", "3"),
+        ];
+        let mut plan = lazy_rebalance(&amount("105"), &assets);
+
+        let unspent = round_to_whole_shares(&assets, &mut plan);
+
+        assert_eq!(deltas(&plan), vec![amount("100"), amount("3")]);
+        assert_eq!(plan.assets[0].shares_to_trade, Some(BigInt::from(10)));
+        assert_eq!(plan.assets[1].shares_to_trade, Some(BigInt::from(1)));
+        assert_eq!(unspent, amount("2"));
+    }
+
+    #[test]
+    fn a_new_value_keeps_the_shares_in_line_with_the_price() {
+        let mut priced = priced_asset("Bonds", "1", "4", "25");
+        assert_eq!(priced.value, amount("100"));
+
+        priced.set_value(amount("150"));
+
+        assert_eq!(priced.shares, Some(amount("6")));
+        assert_eq!(priced.price, Some(amount("25")));
+    }
+
+    #[test]
+    fn an_asset_without_a_price_keeps_its_fractional_trade() {
+        let assets = vec![
+            priced_asset("Stocks", "0.5", "10", "30"),
+            asset("Bonds", "0.5", "300"),
+        ];
+        let mut plan = lazy_rebalance(&amount("100"), &assets);
+
+        let unspent = round_to_whole_shares(&assets, &mut plan);
+
+        assert_eq!(deltas(&plan), vec![amount("30"), amount("50")]);
+        assert_eq!(plan.assets[0].shares_to_trade, Some(BigInt::from(1)));
+        assert_eq!(plan.assets[1].shares_to_trade, None);
+        assert_eq!(unspent, amount("20"));
+    }
+
+    #[test]
+    fn whole_shares_cover_a_withdrawal_the_rounding_left_short() {
+        let assets = vec![
+            priced_asset("Bonds", "0.5", "10", "10"),
+            priced_asset("Stocks", "0.5", "75", "4"),
+        ];
+        let mut plan = withdraw(&amount("105"), &holdings(&assets), &assets).unwrap();
+
+        // 26.25 shares round down to 26 which raises $104, one more share covers the rest
+        let unspent = round_to_whole_shares(&assets, &mut plan);
+
+        assert_eq!(deltas(&plan), vec![amount("0"), amount("-108")]);
+        assert_eq!(plan.assets[1].shares_to_trade, Some(BigInt::from(-27)));
+        assert_eq!(unspent, amount("3"));
+    }
+
+    #[test]
+    fn an_account_never_sells_more_than_it_holds() {
+        let assets = vec![
+            asset("Bonds", "0.5", "1000"),
+            asset("Stocks", "0.5", "1000"),
+        ];
+        let account_values: HashMap<String, BigRational> = vec![
+            ("Bonds".to_string(), amount("100")),
+            ("Stocks".to_string(), amount("1000")),
+        ]
+        .into_iter()
+        .collect();
+
+        let plan = withdraw(&amount("300"), &account_values, &assets).unwrap();
+
+        assert_eq!(deltas(&plan), vec![amount("-100"), amount("-200")]);
+    }
+
+    #[test]
+    fn an_account_only_trades_what_it_holds() {
+        let assets = vec![asset("Bonds", "0.5", "400"), asset("Stocks", "0.5", "500")];
+        let account_values: HashMap<String, BigRational> =
+            vec![("Stocks".to_string(), amount("500"))]
+                .into_iter()
+                .collect();
+
+        let plan = lazy_account_rebalance(&amount("200"), None, &account_values, &assets);
+
+        assert_eq!(deltas(&plan), vec![amount("0"), amount("200")]);
+    }
+
+    fn ratio(numerator: i64, denominator: i64) -> BigRational {
+        BigRational::new(BigInt::from(numerator), BigInt::from(denominator))
+    }
+
+    #[test]
+    fn decimals_parse_exactly() {
+        assert_eq!(parse_decimal("16500.10"), Some(ratio(165001, 10)));
+        assert_eq!(parse_decimal(" $1,000.50 "), Some(ratio(2001, 2)));
+        assert_eq!(parse_decimal("-25"), Some(ratio(-25, 1)));
+        assert_eq!(parse_decimal(".5"), Some(ratio(1, 2)));
+        assert_eq!(parse_decimal("0.1"), Some(ratio(1, 10)));
+
+        for input in ["", "$", "abc", "1.2.3", "1e5", "--5", "12%"] {
+            assert_eq!(parse_decimal(input), None, "{}", input);
+        }
+    }
+
+    #[test]
+    fn rationals_format_rounding_half_away_from_zero() {
+        assert_eq!(format_rational(&ratio(1, 3), 2), "0.33");
+        assert_eq!(format_rational(&ratio(-469, 200), 2), "-2.35");
+        assert_eq!(format_rational(&ratio(1, 20), 2), "0.05");
+        assert_eq!(format_rational(&ratio(5, 2), 0), "3");
+        assert_eq!(format_trimmed(&ratio(20, 1), 3), "20");
+        assert_eq!(format_trimmed(&ratio(25, 2), 3), "12.5");
+    }
+
+    #[test]
+    fn rounding_keeps_the_total() {
+        let thirds = vec![ratio(1, 3), ratio(1, 3), ratio(1, 3)];
+
+        let rounded = round_preserving_total(&thirds, 2);
+
+        assert_eq!(
+            rounded,
+            vec![ratio(34, 100), ratio(33, 100), ratio(33, 100)]
+        );
+    }
+
+    #[test]
+    fn drift_metrics_measure_how_far_the_portfolio_is_off_target() {
+        let assets = vec![asset("Stocks", "0.6", "700"), asset("Bonds", "0.4", "300")];
+
+        let before = drift_metrics(&assets);
+
+        assert_eq!(before.total_drift, ratio(1, 5));
+        assert_eq!(before.max_relative_deviation, ratio(1, 4));
+        assert!((before.rms_drift - 0.1).abs() < 1e-12);
+        assert_eq!(before.cash_to_target, ratio(500, 3));
+
+        let plan = lazy_rebalance(&before.cash_to_target, &assets);
+        let after = drift_metrics_after(&assets, &plan);
+
+        assert!(after.total_drift.is_zero());
+        assert!(after.cash_to_target.is_zero());
+    }
+
+    #[test]
+    fn assets_without_a_target_only_count_towards_the_drift() {
+        let assets = vec![asset("Stocks", "1", "900"), asset("Cash", "0", "100")];
+
+        let metrics = drift_metrics(&assets);
+
+        assert_eq!(metrics.total_drift, ratio(1, 5));
+        assert_eq!(metrics.max_relative_deviation, ratio(1, 10));
+        assert_eq!(metrics.cash_to_target, BigRational::zero());
+        assert!(drift_metrics(&[]).total_drift.is_zero());
+    }
+
+    #[test]
+    fn every_plan_follows_the_callers_order_and_leaves_the_assets_alone() {
+        let assets = vec![
+            asset("Stocks", "0.5", "700"),
+            banded_asset("Bonds", "0.3", "100", "0.05"),
+            asset("TIPS", "0.2", "200"),
+        ];
+        let snapshot = |assets: &[Asset]| -> Vec<(String, BigRational)> {
+            assets
+                .iter()
+                .map(|asset| (asset.name.clone(), asset.value.clone()))
+                .collect()
+        };
+        let before = snapshot(&assets);
+
+        let plans = vec![
+            lazy_rebalance(&amount("300"), &assets),
+            full_rebalance(&amount("300"), &assets),
+            band_rebalance(&amount("300"), BandTarget::Edge, &assets),
+            withdraw(&amount("100"), &holdings(&assets), &assets).unwrap(),
+        ];
+
+        assert_eq!(snapshot(&assets), before);
+        for plan in &plans {
+            let names: Vec<&str> = plan
+                .assets
+                .iter()
+                .map(|trade| trade.name.as_str())
+                .collect();
+            assert_eq!(names, ["Stocks", "Bonds", "TIPS"]);
+        }
+        assert_eq!(
+            deltas(&plans[1]),
+            vec![amount("-50"), amount("290"), amount("60")]
+        );
+    }
+}
@@ -0,0 +1,260 @@
+use crate::rebalance::{
+    drift_metrics, drift_metrics_after, format_rational, portfolio_total, to_report_rows,
+    to_string, Asset, AssetPlan, DriftMetrics, RebalancePlan,
+};
+use num::{BigRational, Zero};
+use serde::{Deserialize, Serialize};
+use std::{fmt, str::FromStr};
+
+/// How the headless plan prints its results
+/// Table is the tab aligned report, Markdown the same report as a markdown table
+/// Json and Csv carry every amount as an exact rational for downstream tools
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OutputFormat {
+    Table,
+    Markdown,
+    Json,
+    Csv,
+}
+
+impl fmt::Display for OutputFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            OutputFormat::Table => write!(f, "table"),
+            OutputFormat::Markdown => write!(f, "markdown"),
+            OutputFormat::Json => write!(f, "json"),
+            OutputFormat::Csv => write!(f, "csv"),
+        }
+    }
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "table" => Ok(OutputFormat::Table),
+            "markdown" => Ok(OutputFormat::Markdown),
+            "json" => Ok(OutputFormat::Json),
+            "csv" => Ok(OutputFormat::Csv),
+            _ => Err(format!("unknown output format '{}'", s)),
+        }
+    }
+}
+
+/// The rebalance result of one asset. Amounts are exact rationals written as strings such as
+/// `16500` or `33001/2`, allocations are fractions of the portfolio (0.25 rather than 25%)
+#[derive(Serialize, Deserialize)]
+pub struct AssetResult {
+    pub name: String,
+    pub value: String,
+    pub actual_allocation: String,
+    pub new_allocation: String,
+    pub target_allocation: String,
+    pub target_value: String,
+    pub delta: String,
+    /// the trade left out for being under the minimum trade
+    pub dropped_delta: Option<String>,
+    pub shares_to_trade: Option<String>,
+}
+
+impl AssetResult {
+    /// `new_total` is the value of the whole portfolio after the trades
+    pub fn new(asset: &Asset, trade: &AssetPlan, new_total: &BigRational) -> Self {
+        let new_allocation = if new_total.is_zero() {
+            BigRational::zero()
+        } else {
+            (&asset.value + &trade.delta) / new_total
+        };
+
+        AssetResult {
+            name: asset.name.clone(),
+            value: asset.value.to_string(),
+            actual_allocation: trade.actual_allocation.to_string(),
+            new_allocation: new_allocation.to_string(),
+            target_allocation: asset.target_allocation_percent.to_string(),
+            target_value: trade.target_value.to_string(),
+            delta: trade.delta.to_string(),
+            dropped_delta: trade.dropped_delta.as_ref().map(|delta| delta.to_string()),
+            shares_to_trade: trade
+                .shares_to_trade
+                .as_ref()
+                .map(|shares| shares.to_string()),
+        }
+    }
+}
+
+/// Drift metrics as written by the json format, rationals as strings like the asset results
+#[derive(Serialize)]
+pub struct MetricsResult {
+    pub total_drift: String,
+    pub max_relative_deviation: String,
+    pub rms_drift: f64,
+    pub cash_to_target: String,
+}
+
+impl From<DriftMetrics> for MetricsResult {
+    fn from(metrics: DriftMetrics) -> Self {
+        MetricsResult {
+            total_drift: metrics.total_drift.to_string(),
+            max_relative_deviation: metrics.max_relative_deviation.to_string(),
+            rms_drift: metrics.rms_drift,
+            cash_to_target: metrics.cash_to_target.to_string(),
+        }
+    }
+}
+
+/// The whole plan as written by the json format
+#[derive(Serialize)]
+struct Plan {
+    assets: Vec<AssetResult>,
+    unspent_cash: Option<String>,
+    drift_before: MetricsResult,
+    drift_after: MetricsResult,
+}
+
+/// The results of every asset of the portfolio `plan` was made for
+pub fn to_results(assets: &[Asset], plan: &RebalancePlan) -> Vec<AssetResult> {
+    let new_total = plan
+        .assets
+        .iter()
+        .fold(portfolio_total(assets), |total, trade| total + &trade.delta);
+
+    assets
+        .iter()
+        .zip(&plan.assets)
+        .map(|(asset, trade)| AssetResult::new(asset, trade, &new_total))
+        .collect()
+}
+
+/// Renders the plan for the portfolio `assets` in the chosen format, along with the cash the rebalance
+/// couldn't spend when there is any worth reporting and the drift before and after the trades.
+/// The csv format only lists the assets
+pub fn render(
+    format: OutputFormat,
+    assets: &[Asset],
+    plan: &RebalancePlan,
+    unspent_cash: Option<&BigRational>,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let mut notes = vec![];
+    if let Some(cash) = unspent_cash {
+        notes.push(format!("Unspent cash: {}", format_rational(cash, 2)));
+    }
+    notes.extend(drift_lines(assets, plan));
+
+    Ok(match format {
+        OutputFormat::Table => format!("{}\n\n{}\n", to_string(assets, plan), notes.join("\n")),
+        OutputFormat::Markdown => {
+            format!("{}\n{}\n", to_markdown(assets, plan), notes.join("\n\n"))
+        }
+        OutputFormat::Json => {
+            let plan = Plan {
+                assets: to_results(assets, plan),
+                unspent_cash: unspent_cash.map(|cash| cash.to_string()),
+                drift_before: drift_metrics(assets).into(),
+                drift_after: drift_metrics_after(assets, plan).into(),
+            };
+            format!("{}\n", serde_json::to_string_pretty(&plan)?)
+        }
+        OutputFormat::Csv => {
+            let mut wtr = csv::Writer::from_writer(vec![]);
+            for result in to_results(assets, plan) {
+                wtr.serialize(result)?;
+            }
+            String::from_utf8(wtr.into_inner()?)?
+        }
+    })
+}
+
+/// A line each for the drift before and after the trades
+pub fn drift_lines(assets: &[Asset], plan: &RebalancePlan) -> Vec<String> {
+    vec![
+        format!("Drift before the trades: {}", drift_metrics(assets)),
+        format!(
+            "Drift after the trades: {}",
+            drift_metrics_after(assets, plan)
+        ),
+    ]
+}
+
+/// The rebalance report as a markdown table, the numbers are right aligned
+pub fn to_markdown(assets: &[Asset], plan: &RebalancePlan) -> String {
+    let rows = to_report_rows(assets, plan);
+    let line = |cells: &[String]| {
+        let cells: Vec<String> = cells.iter().map(|cell| cell.replace('|', "\\|")).collect();
+        format!("| {} |\n", cells.join(" | "))
+    };
+
+    let mut markdown = line(&rows[0]);
+    let alignment: Vec<String> = (0..rows[0].len())
+        .map(|column| if column == 0 { "---" } else { "---:" }.to_string())
+        .collect();
+    markdown.push_str(&line(&alignment));
+
+    for row in &rows[1..] {
+        markdown.push_str(&line(row));
+    }
+
+    markdown
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rebalance::{lazy_rebalance, parse_decimal};
+
+    fn amount(input: &str) -> BigRational {
+        parse_decimal(input).unwrap()
+    }
+
+    fn portfolio() -> (Vec<Asset>, RebalancePlan) {
+        let assets = vec![
+            Asset::new("Bonds".to_string(), amount("0.5"), amount("400")),
+            Asset::new("Stocks | ETF".to_string(), amount("0.5"), amount("500")),
+        ];
+        let plan = lazy_rebalance(&amount("200"), &assets);
+        (assets, plan)
+    }
+
+    #[test]
+    fn csv_lists_every_asset_as_exact_rationals() {
+        let (assets, plan) = portfolio();
+
+        let csv = render(OutputFormat::Csv, &assets, &plan, Some(&amount("1"))).unwrap();
+
+        assert_eq!(
+            csv,
+            "name,value,actual_allocation,new_allocation,target_allocation,target_value,delta,\
+             dropped_delta,shares_to_trade\n\
+             Bonds,400,4/9,1/2,1/2,550,150,,\n\
+             Stocks | ETF,500,5/9,1/2,1/2,550,50,,\n"
+        );
+    }
+
+    #[test]
+    fn json_carries_the_assets_unspent_cash_and_drift() {
+        let (assets, plan) = portfolio();
+
+        let json = render(OutputFormat::Json, &assets, &plan, Some(&amount("0.25"))).unwrap();
+        let json: serde_json::Value = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(json["assets"][0]["name"], "Bonds");
+        assert_eq!(json["assets"][0]["delta"], "150");
+        assert_eq!(json["assets"][1]["new_allocation"], "1/2");
+        assert_eq!(json["unspent_cash"], "1/4");
+        assert_eq!(json["drift_before"]["total_drift"], "1/9");
+        assert_eq!(json["drift_after"]["total_drift"], "0");
+    }
+
+    #[test]
+    fn markdown_right_aligns_the_numbers_and_escapes_pipes() {
+        let (assets, plan) = portfolio();
+
+        let markdown = to_markdown(&assets, &plan);
+        let lines: Vec<&str> = markdown.lines().collect();
+
+        assert!(lines[0].starts_with("| Asset name | "));
+        assert!(lines[1].starts_with("| --- | ---: |"));
+        assert!(lines[3].starts_with("| Stocks \\| ETF | "));
+    }
+}
@@ -0,0 +1,469 @@
+use crate::error::{read_rows, LoadError};
+use crate::rebalance::{
+    drift_metrics_after, format_rational, lazy_rebalance, parse_decimal, round_to_whole_shares,
+    Asset, RebalancePlan,
+};
+use chrono::{Days, Months, NaiveDate};
+use num::{BigInt, BigRational, One, Zero};
+use serde::Serialize;
+use std::{collections::HashMap, fmt, io::Write, str::FromStr};
+use tabwriter::TabWriter;
+
+/// How often a schedule contributes
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Frequency {
+    Weekly,
+    Biweekly,
+    Monthly,
+}
+
+impl Frequency {
+    pub fn per_year(self) -> u32 {
+        match self {
+            Frequency::Weekly => 52,
+            Frequency::Biweekly => 26,
+            Frequency::Monthly => 12,
+        }
+    }
+
+    /// The date of the contribution `periods` after the one on `start`
+    pub fn advance(self, start: NaiveDate, periods: u32) -> NaiveDate {
+        match self {
+            Frequency::Weekly => start + Days::new(7 * u64::from(periods)),
+            Frequency::Biweekly => start + Days::new(14 * u64::from(periods)),
+            Frequency::Monthly => start + Months::new(periods),
+        }
+    }
+}
+
+impl fmt::Display for Frequency {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Frequency::Weekly => write!(f, "weekly"),
+            Frequency::Biweekly => write!(f, "biweekly"),
+            Frequency::Monthly => write!(f, "monthly"),
+        }
+    }
+}
+
+impl FromStr for Frequency {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "weekly" => Ok(Frequency::Weekly),
+            "biweekly" => Ok(Frequency::Biweekly),
+            "monthly" => Ok(Frequency::Monthly),
+            _ => Err(format!("unknown frequency '{}'", s)),
+        }
+    }
+}
+
+/// A fixed contribution made every period for a number of months, e.g. $2,000 biweekly for
+/// 6 months
+#[derive(Clone)]
+pub struct Schedule {
+    /// cash contributed every period
+    pub amount: BigRational,
+    pub frequency: Frequency,
+    pub months: u32,
+}
+
+impl Schedule {
+    /// Spreads `total` evenly over the contributions of the schedule
+    pub fn spread(total: &BigRational, frequency: Frequency, months: u32) -> Self {
+        let mut schedule = Schedule {
+            amount: BigRational::zero(),
+            frequency,
+            months,
+        };
+        if schedule.periods() > 0 {
+            schedule.amount = total / BigRational::from_integer(BigInt::from(schedule.periods()));
+        }
+
+        schedule
+    }
+
+    /// The number of contributions, e.g. 13 for biweekly over 6 months
+    pub fn periods(&self) -> u32 {
+        self.months * self.frequency.per_year() / 12
+    }
+
+    /// Cash contributed over the whole schedule
+    pub fn total(&self) -> BigRational {
+        &self.amount * BigRational::from_integer(BigInt::from(self.periods()))
+    }
+}
+
+impl fmt::Display for Schedule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "${} {} for {} months",
+            format_rational(&self.amount, 2),
+            self.frequency,
+            self.months
+        )
+    }
+}
+
+/// Parses the schedule typed into the TUI: the amount, the frequency and the number of months
+/// separated by commas, e.g. `2000, biweekly, 6`
+impl FromStr for Schedule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let columns: Vec<&str> = s.split(',').map(str::trim).collect();
+        let (amount, frequency, months) = match columns[..] {
+            [amount, frequency, months] => (amount, frequency, months),
+            _ => {
+                return Err("Enter the amount, frequency and months separated by commas".to_string())
+            }
+        };
+
+        let amount = parse_amount(amount)?;
+        let frequency = frequency.parse()?;
+        let months = match months.parse::<u32>() {
+            Ok(months) if months > 0 => months,
+            _ => return Err(format!("{} is not a number of months", months)),
+        };
+
+        Ok(Schedule {
+            amount,
+            frequency,
+            months,
+        })
+    }
+}
+
+fn parse_amount(text: &str) -> Result<BigRational, String> {
+    parse_decimal(text)
+        .filter(|amount| *amount > BigRational::zero())
+        .ok_or_else(|| format!("{} is not a dollar amount", text))
+}
+
+/// One contribution of a schedule and the purchases it makes
+pub struct SchedulePeriod {
+    /// counted from 1
+    pub number: u32,
+    pub date: NaiveDate,
+    /// the portfolio before the contribution, grown by the returns of the earlier periods
+    pub assets: Vec<Asset>,
+    /// the purchases of the period, the contribution includes the cash carried over from the
+    /// period before
+    pub plan: RebalancePlan,
+    /// cash the whole share purchases left over, it is carried into the next period
+    pub unspent_cash: BigRational,
+}
+
+/// Lazy rebalances every contribution of `schedule` in turn, the first one on `start`.
+/// Prices stay flat unless `returns` gives an asset an expected annual return (0.07 for 7%),
+/// which is compounded every period at the annual return divided by the number of periods in a
+/// year. Grown values and prices are kept to the cent
+pub fn plan_schedule(
+    assets: &[Asset],
+    schedule: &Schedule,
+    returns: &HashMap<String, BigRational>,
+    start: NaiveDate,
+) -> Vec<SchedulePeriod> {
+    let per_year = BigRational::from_integer(BigInt::from(schedule.frequency.per_year()));
+
+    let mut portfolio = assets.to_vec();
+    let mut carried_cash = BigRational::zero();
+    let mut periods = vec![];
+
+    for number in 1..=schedule.periods() {
+        let contribution = &schedule.amount + &carried_cash;
+        let mut plan = lazy_rebalance(&contribution, &portfolio);
+        let unspent_cash = round_to_whole_shares(&portfolio, &mut plan);
+
+        let mut next = portfolio.clone();
+        for (asset, trade) in next.iter_mut().zip(&plan.assets) {
+            asset.set_value(&asset.value + &trade.delta);
+
+            if let Some(annual_return) = returns.get(&asset.name) {
+                let growth = BigRational::one() + annual_return / &per_year;
+                match (asset.shares.clone(), asset.price.clone()) {
+                    (Some(shares), Some(price)) => {
+                        asset.set_holding(shares, to_cents(&(price * growth)))
+                    }
+                    _ => asset.set_value(to_cents(&(&asset.value * growth))),
+                }
+            }
+        }
+
+        carried_cash = unspent_cash.clone();
+        periods.push(SchedulePeriod {
+            number,
+            date: schedule.frequency.advance(start, number - 1),
+            assets: portfolio,
+            plan,
+            unspent_cash,
+        });
+        portfolio = next;
+    }
+
+    periods
+}
+
+fn to_cents(amount: &BigRational) -> BigRational {
+    let hundred = BigRational::from_integer(BigInt::from(100));
+    (amount * &hundred).round() / hundred
+}
+
+/// Reads the expected returns file, each row is an asset and its expected annual return in
+/// percent, e.g. `Domestic Stock ETF, 7`. Returns are given back as fractions
+pub fn read_returns(path: &str) -> Result<HashMap<String, BigRational>, Vec<LoadError>> {
+    let mut errors = vec![];
+    let rows = read_rows(path, &mut errors);
+    let hundred = BigRational::from_integer(BigInt::from(100));
+
+    let mut returns = HashMap::new();
+    for row in rows {
+        match (row.text(0), row.decimal(1)) {
+            (Ok(name), Ok(percent)) => {
+                returns.insert(name.to_string(), percent / &hundred);
+            }
+            (Err(error), _) | (_, Err(error)) => errors.push(error),
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(returns)
+    } else {
+        Err(errors)
+    }
+}
+
+/// One purchase of a schedule as written by the csv export. Amounts are exact rationals
+/// written as strings like the plan's csv format, the allocation is a fraction of the portfolio
+#[derive(Serialize)]
+pub struct PurchaseResult {
+    pub period: u32,
+    pub date: String,
+    pub name: String,
+    pub amount: String,
+    pub shares: Option<String>,
+    pub value_after: String,
+    pub new_allocation: String,
+    pub target_allocation: String,
+}
+
+/// Every purchase of the schedule, periods in order and the assets of a period in portfolio
+/// order. Assets a period doesn't buy are left out
+pub fn to_purchases(periods: &[SchedulePeriod]) -> Vec<PurchaseResult> {
+    let mut purchases = vec![];
+
+    for period in periods {
+        let new_total = period
+            .assets
+            .iter()
+            .zip(&period.plan.assets)
+            .fold(BigRational::zero(), |total, (asset, trade)| {
+                total + &asset.value + &trade.delta
+            });
+
+        for (asset, trade) in period.assets.iter().zip(&period.plan.assets) {
+            if trade.delta.is_zero() {
+                continue;
+            }
+
+            let value_after = &asset.value + &trade.delta;
+            let new_allocation = if new_total.is_zero() {
+                BigRational::zero()
+            } else {
+                &value_after / &new_total
+            };
+
+            purchases.push(PurchaseResult {
+                period: period.number,
+                date: period.date.to_string(),
+                name: asset.name.clone(),
+                amount: trade.delta.to_string(),
+                shares: trade.shares_to_trade.as_ref().map(BigInt::to_string),
+                value_after: value_after.to_string(),
+                new_allocation: new_allocation.to_string(),
+                target_allocation: asset.target_allocation_percent.to_string(),
+            });
+        }
+    }
+
+    purchases
+}
+
+/// The cells of the schedule table: a header row and a row per purchase, amounts to the cent
+/// and allocations in percent
+pub fn to_schedule_rows(periods: &[SchedulePeriod]) -> Vec<Vec<String>> {
+    let mut rows: Vec<Vec<String>> = vec![[
+        "Period",
+        "Date",
+        "Asset name",
+        "$ to buy",
+        "Shares to buy",
+        "Value after",
+        "New holdings %",
+        "Target allocation %",
+    ]
+    .iter()
+    .map(|header| header.to_string())
+    .collect()];
+
+    let hundred = BigRational::from_integer(BigInt::from(100));
+    let amount = |text: &str| format_rational(&BigRational::from_str(text).unwrap(), 2);
+    let percent =
+        |text: &str| format_rational(&(BigRational::from_str(text).unwrap() * &hundred), 3);
+
+    for purchase in to_purchases(periods) {
+        rows.push(vec![
+            purchase.period.to_string(),
+            purchase.date,
+            purchase.name,
+            amount(&purchase.amount),
+            purchase.shares.unwrap_or_default(),
+            amount(&purchase.value_after),
+            percent(&purchase.new_allocation),
+            percent(&purchase.target_allocation),
+        ]);
+    }
+
+    rows
+}
+
+/// What the schedule adds up to: the cash contributed, the cash left unspent at the end and
+/// the drift once the last purchase is made
+pub fn schedule_summary(schedule: &Schedule, periods: &[SchedulePeriod]) -> Vec<String> {
+    let mut lines = vec![format!(
+        "Contributed: ${} over {} periods",
+        format_rational(&schedule.total(), 2),
+        periods.len()
+    )];
+
+    if let Some(last) = periods.last() {
+        if !last.unspent_cash.is_zero() {
+            lines.push(format!(
+                "Unspent cash: {}",
+                format_rational(&last.unspent_cash, 2)
+            ));
+        }
+        lines.push(format!(
+            "Drift after the last purchase: {}",
+            drift_metrics_after(&last.assets, &last.plan)
+        ));
+    }
+
+    lines
+}
+
+/// The schedule as a tab aligned table followed by its summary
+pub fn schedule_to_string(schedule: &Schedule, periods: &[SchedulePeriod]) -> String {
+    let buf = to_schedule_rows(periods)
+        .iter()
+        .map(|row| row.join("\t"))
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    let mut tw = TabWriter::new(vec![]);
+
+    tw.write_all(buf.as_bytes()).unwrap();
+    tw.flush().unwrap();
+
+    format!(
+        "{}\n\n{}\n",
+        String::from_utf8(tw.into_inner().unwrap()).unwrap(),
+        schedule_summary(schedule, periods).join("\n")
+    )
+}
+
+/// The purchases of the schedule in csv, one row per purchase
+pub fn schedule_to_csv(periods: &[SchedulePeriod]) -> Result<String, Box<dyn std::error::Error>> {
+    let mut wtr = csv::Writer::from_writer(vec![]);
+    for purchase in to_purchases(periods) {
+        wtr.serialize(purchase)?;
+    }
+
+    Ok(String::from_utf8(wtr.into_inner()?)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn amount(input: &str) -> BigRational {
+        parse_decimal(input).unwrap()
+    }
+
+    fn deltas(period: &SchedulePeriod) -> Vec<BigRational> {
+        period
+            .plan
+            .assets
+            .iter()
+            .map(|trade| trade.delta.clone())
+            .collect()
+    }
+
+    #[test]
+    fn a_total_is_spread_over_every_contribution() {
+        let schedule = Schedule::spread(&amount("6000"), Frequency::Biweekly, 6);
+
+        assert_eq!(schedule.periods(), 13);
+        assert_eq!(schedule.amount, amount("6000") / amount("13"));
+        assert_eq!(schedule.total(), amount("6000"));
+        assert_eq!(
+            Schedule::spread(&amount("6000"), Frequency::Weekly, 0).amount,
+            amount("0")
+        );
+    }
+
+    #[test]
+    fn each_contribution_is_lazy_rebalanced_on_its_own_date() {
+        let assets = vec![
+            Asset::new("Bonds".to_string(), amount("0.5"), amount("400")),
+            Asset::new("Stocks".to_string(), amount("0.5"), amount("500")),
+        ];
+        let schedule: Schedule = "100, monthly, 3".parse().unwrap();
+        let start = NaiveDate::from_ymd_opt(2024, 1, 31).unwrap();
+
+        let periods = plan_schedule(&assets, &schedule, &HashMap::new(), start);
+
+        let dates: Vec<String> = periods
+            .iter()
+            .map(|period| period.date.to_string())
+            .collect();
+        assert_eq!(dates, ["2024-01-31", "2024-02-29", "2024-03-31"]);
+        assert_eq!(deltas(&periods[0]), vec![amount("100"), amount("0")]);
+        assert_eq!(deltas(&periods[1]), vec![amount("50"), amount("50")]);
+        assert_eq!(periods[2].assets[0].value, amount("550"));
+    }
+
+    #[test]
+    fn cash_left_by_whole_shares_is_carried_into_the_next_contribution() {
+        let mut stocks = Asset::new("Stocks".to_string(), amount("1"), amount("0"));
+        stocks.set_holding(amount("0"), amount("30"));
+        let schedule = Schedule {
+            amount: amount("50"),
+            frequency: Frequency::Monthly,
+            months: 2,
+        };
+        let start = NaiveDate::from_ymd_opt(2024, 1, 1).unwrap();
+
+        let periods = plan_schedule(&[stocks], &schedule, &HashMap::new(), start);
+
+        assert_eq!(periods[0].unspent_cash, amount("20"));
+        assert_eq!(periods[1].plan.contribution, amount("70"));
+        assert_eq!(deltas(&periods[1]), vec![amount("60")]);
+        assert_eq!(periods[1].unspent_cash, amount("10"));
+    }
+
+    #[test]
+    fn a_schedule_needs_an_amount_a_frequency_and_months() {
+        let schedule: Schedule = "$2000, biweekly, 6".parse().unwrap();
+        assert_eq!(schedule.amount, amount("2000"));
+        assert_eq!(schedule.frequency, Frequency::Biweekly);
+        assert_eq!(schedule.months, 6);
+
+        assert!("2000, biweekly".parse::<Schedule>().is_err());
+        assert!("-5, weekly, 6".parse::<Schedule>().is_err());
+        assert!("2000, daily, 6".parse::<Schedule>().is_err());
+        assert!("2000, weekly, 0".parse::<Schedule>().is_err());
+    }
+}
@@ -0,0 +1,197 @@
+use chrono::Local;
+use std::{
+    error::Error,
+    fs::{self, File},
+    io::{self, ErrorKind, Write},
+    path::Path,
+};
+
+/// How many backups of each saved file are kept, the oldest are removed first
+const BACKUPS_KEPT: usize = 5;
+
+/// A line of a CSV file that is about to be rewritten
+pub enum CsvLine {
+    /// a row the app reads and may rewrite
+    Row(csv::StringRecord),
+    /// a comment starting with '#', a blank line or anything the CSV reader can't parse,
+    /// saved back untouched
+    Text(String),
+}
+
+/// Reads a CSV file line by line so it can be saved back with everything the app doesn't
+/// manage left in place. A missing file has no lines, any other error reading it is returned so
+/// the save stops rather than writing over lines it couldn't read
+pub fn read_lines(path: &str) -> io::Result<Vec<CsvLine>> {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(error) if error.kind() == ErrorKind::NotFound => String::new(),
+        Err(error) => return Err(error),
+    };
+
+    Ok(text
+        .lines()
+        .map(|line| {
+            if line.trim().is_empty() || line.starts_with('#') {
+                return CsvLine::Text(line.to_string());
+            }
+
+            let mut reader = csv::ReaderBuilder::new()
+                .has_headers(false)
+                .flexible(true)
+                .from_reader(line.as_bytes());
+            match reader.records().next() {
+                Some(Ok(record)) => CsvLine::Row(record),
+                _ => CsvLine::Text(line.to_string()),
+            }
+        })
+        .collect())
+}
+
+/// Writes the fields as a single CSV line, quoting only where needed
+pub fn to_line(fields: &[String]) -> Result<String, Box<dyn Error>> {
+    let mut wtr = csv::WriterBuilder::new().flexible(true).from_writer(vec![]);
+    wtr.write_record(fields)?;
+
+    let line = String::from_utf8(wtr.into_inner()?)?;
+    Ok(line.trim_end_matches(&['\r', '\n'][..]).to_string())
+}
+
+/// Gives each new field the leading spaces of the field it replaces, so columns lined up by
+/// hand stay lined up
+pub fn pad_like(record: &csv::StringRecord, fields: Vec<String>) -> Vec<String> {
+    fields
+        .into_iter()
+        .enumerate()
+        .map(|(index, field)| match record.get(index) {
+            Some(old) if !field.is_empty() && !field.starts_with(char::is_whitespace) => {
+                let padding = &old[..old.len() - old.trim_start().len()];
+                format!("{}{}", padding, field)
+            }
+            _ => field,
+        })
+        .collect()
+}
+
+/// Replaces the file with the given lines. The current file is first copied to a timestamped
+/// backup, e.g. `portfolio.csv.20261017-154312.123.bak`, and the new contents are written to a
+/// temporary file that is then renamed over the original, so a crash never leaves a half
+/// written file behind
+pub fn save_lines(path: &str, lines: &[String]) -> Result<(), Box<dyn Error>> {
+    let mut contents = lines.join("\n");
+    if !contents.is_empty() {
+        contents.push('\n');
+    }
+
+    if Path::new(path).exists() {
+        backup(path)?;
+    }
+
+    let temp_path = format!("{}.tmp", path);
+    let written = File::create(&temp_path).and_then(|mut file| {
+        file.write_all(contents.as_bytes())?;
+        file.sync_all()
+    });
+    if let Err(error) = written.and_then(|()| fs::rename(&temp_path, path)) {
+        let _ = fs::remove_file(&temp_path);
+        return Err(error.into());
+    }
+
+    Ok(())
+}
+
+/// Copies the file to a new timestamped backup and removes all but the newest backups
+fn backup(path: &str) -> Result<(), Box<dyn Error>> {
+    let timestamp = Local::now().format("%Y%m%d-%H%M%S%.3f");
+    fs::copy(path, format!("{}.{}.bak", path, timestamp))?;
+
+    let path = Path::new(path);
+    let directory = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    let prefix = format!("{}.", path.file_name().unwrap().to_string_lossy());
+
+    // the timestamps sort in the order the backups were made
+    let mut backups: Vec<_> = fs::read_dir(directory)?
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|backup| {
+            let name = backup.file_name().unwrap().to_string_lossy();
+            name.starts_with(&prefix) && name.ends_with(".bak")
+        })
+        .collect();
+    backups.sort();
+
+    if backups.len() > BACKUPS_KEPT {
+        for old in &backups[..backups.len() - BACKUPS_KEPT] {
+            fs::remove_file(old)?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, thread, time::Duration};
+
+    #[test]
+    fn a_missing_file_has_no_lines() {
+        let path = env::temp_dir().join("tui-rebalance-missing.csv");
+
+        assert!(read_lines(path.to_str().unwrap()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn a_file_that_cannot_be_read_is_an_error() {
+        let path = env::temp_dir().join("tui-rebalance-latin1.csv");
+        fs::write(&path, b"Caf\xe9 fund, $100\n").unwrap();
+
+        let lines = read_lines(path.to_str().unwrap());
+        fs::remove_file(&path).unwrap();
+
+        assert!(lines.is_err());
+    }
+
+    #[test]
+    fn saving_keeps_the_five_newest_backups() {
+        let directory = env::temp_dir().join("tui-rebalance-backups");
+        let _ = fs::remove_dir_all(&directory);
+        fs::create_dir_all(&directory).unwrap();
+        let path = directory.join("portfolio.csv");
+        let path = path.to_str().unwrap();
+        fs::write(directory.join("accounts.csv.20200101-000000.000.bak"), "").unwrap();
+
+        for save in 0..8 {
+            save_lines(path, &[format!("Bond fund, {}", save)]).unwrap();
+            // backups are named to the millisecond
+            thread::sleep(Duration::from_millis(5));
+        }
+
+        let mut names: Vec<String> = fs::read_dir(&directory)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+            .collect();
+        names.sort();
+        let backups: Vec<String> = names
+            .iter()
+            .filter(|name| name.starts_with("portfolio.csv.") && name.ends_with(".bak"))
+            .map(|name| fs::read_to_string(directory.join(name)).unwrap())
+            .collect();
+        let saved = fs::read_to_string(path).unwrap();
+        fs::remove_dir_all(&directory).unwrap();
+
+        assert_eq!(saved, "Bond fund, 7\n");
+        assert_eq!(
+            backups,
+            ["2", "3", "4", "5", "6"]
+                .iter()
+                .map(|save| format!("Bond fund, {}\n", save))
+                .collect::<Vec<String>>()
+        );
+        // other files' backups are left alone and no temporary file is left behind
+        assert_eq!(names.len(), 7);
+        assert!(names.contains(&"accounts.csv.20200101-000000.000.bak".to_string()));
+    }
+}
@@ -0,0 +1,647 @@
+use crate::error::{read_rows, LoadError, Row};
+use crate::portfolio::{Percent, Target};
+use crate::rebalance::{format_rational, format_trimmed, parse_decimal, portfolio_total, Asset};
+use crate::store::{pad_like, read_lines, save_lines, to_line, CsvLine};
+use num::{BigInt, BigRational, Zero};
+use std::{collections::HashMap, error::Error};
+
+/// A node of the target allocation tree, e.g. Stocks -> US -> Domestic Stock ETF.
+/// Leaves are the funds held in the portfolio, every other node is an asset class
+#[derive(Clone)]
+pub struct TargetNode {
+    pub name: String,
+    /// the allocation is the share of the parent node, or of the portfolio at the top level
+    pub target: Target,
+    pub children: Vec<TargetNode>,
+    /// hides the children in the Targets pane
+    pub collapsed: bool,
+}
+
+impl TargetNode {
+    fn new(name: String, target: Target) -> TargetNode {
+        TargetNode {
+            name,
+            target,
+            children: vec![],
+            collapsed: false,
+        }
+    }
+
+    /// Whether the node is just a grouping of its children that needs no row in the targets file
+    fn is_plain_grouping(&self) -> bool {
+        let target = &self.target;
+        !self.children.is_empty()
+            && target.allocation.0 == hundred()
+            && target.absolute_band.is_none()
+            && target.relative_band.is_none()
+            && target.minimum_trade.is_none()
+    }
+
+    /// Names of the funds under this node
+    fn leaf_names(&self) -> Vec<&str> {
+        if self.children.is_empty() {
+            return vec![&self.name];
+        }
+
+        self.children
+            .iter()
+            .flat_map(|child| child.leaf_names())
+            .collect()
+    }
+}
+
+/// Reads the targets file into a tree. Nested rows name their parents with '/' separators,
+/// e.g. `Stocks/US, 60` is 60% of the Stocks row and `Stocks/US/Domestic Stock ETF, 100` is a
+/// fund making up all of US stocks. A file without any '/' is a flat list of funds. Parents
+/// that are never given a row of their own are plain groupings at 100%. A fund can only be
+/// listed once, even under different asset classes. Every bad row is reported, not just the
+/// first
+pub fn create_target_tree(path_to_targets: &str) -> Result<Vec<TargetNode>, Vec<LoadError>> {
+    let mut errors = vec![];
+    let rows = read_rows(path_to_targets, &mut errors);
+
+    let mut tree: Vec<TargetNode> = vec![];
+    // nodes left out of the tree, their children are left out along with them
+    let mut skipped: Vec<Vec<String>> = vec![];
+    // the rows that made it into the tree, to look for funds listed twice
+    let mut inserted: Vec<(&Row, Vec<String>)> = vec![];
+
+    for row in &rows {
+        let path: Vec<String> = match row.text(0) {
+            Ok(name) => name
+                .split('/')
+                .map(|segment| segment.trim().to_string())
+                .collect(),
+            Err(error) => {
+                errors.push(error);
+                continue;
+            }
+        };
+
+        if skipped.iter().any(|prefix| path.starts_with(prefix)) {
+            continue;
+        }
+
+        let target = match read_target(row) {
+            Ok(Some(target)) => target,
+            Ok(None) => {
+                skipped.push(path);
+                continue;
+            }
+            Err(error) => {
+                errors.push(error);
+                continue;
+            }
+        };
+
+        insert_node(&mut tree, &path, target);
+        inserted.push((row, path));
+    }
+
+    let mut leaf_paths = vec![];
+    collect_leaf_paths(&tree, &mut vec![], &mut leaf_paths);
+
+    let mut funds: HashMap<&str, &[String]> = HashMap::new();
+    for (row, path) in &inserted {
+        if !leaf_paths.contains(path) {
+            continue;
+        }
+
+        let name = path.last().unwrap();
+        match funds.get(name.as_str()) {
+            Some(other) => errors.push(row.bad_entry(format!(
+                "{} is already listed as {}",
+                name,
+                other.join("/")
+            ))),
+            None => {
+                funds.insert(name, path);
+            }
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(tree)
+    } else {
+        Err(errors)
+    }
+}
+
+fn collect_leaf_paths(
+    nodes: &[TargetNode],
+    parents: &mut Vec<String>,
+    leaf_paths: &mut Vec<Vec<String>>,
+) {
+    for node in nodes {
+        parents.push(node.name.clone());
+        if node.children.is_empty() {
+            leaf_paths.push(parents.clone());
+        } else {
+            collect_leaf_paths(&node.children, parents, leaf_paths);
+        }
+        parents.pop();
+    }
+}
+
+/// Reads the target of a row, None when the allocation is 0 and the row is left out
+fn read_target(row: &Row) -> Result<Option<Target>, LoadError> {
+    let allocation = row.percent(1)?;
+
+    if allocation <= BigRational::zero() {
+        return Ok(None);
+    }
+
+    // the band and minimum trade columns are optional and may be left blank
+    Ok(Some(Target {
+        allocation: Percent(allocation),
+        absolute_band: row.optional_decimal(2)?.map(Percent),
+        relative_band: row.optional_decimal(3)?.map(Percent),
+        minimum_trade: row.optional_decimal(4)?,
+    }))
+}
+
+/// Adds a node at a path of names, creating missing parents as 100% groupings
+pub fn insert_node(nodes: &mut Vec<TargetNode>, path: &[String], target: Target) {
+    let (name, parents) = path.split_last().unwrap();
+
+    let mut nodes = nodes;
+    for parent in parents {
+        let index = match nodes.iter().position(|node| &node.name == parent) {
+            Some(index) => index,
+            None => {
+                nodes.push(TargetNode::new(parent.clone(), grouping_target()));
+                nodes.len() - 1
+            }
+        };
+        nodes = &mut nodes[index].children;
+    }
+
+    match nodes.iter_mut().find(|node| &node.name == name) {
+        // a grouping created for an earlier child gets its real target
+        Some(node) => node.target = target,
+        None => nodes.push(TargetNode::new(name.clone(), target)),
+    }
+}
+
+/// Removes the fund with the given name along with any grouping it leaves empty. Returns
+/// whether the fund was found
+pub fn remove_leaf(nodes: &mut Vec<TargetNode>, name: &str) -> bool {
+    if let Some(index) = nodes
+        .iter()
+        .position(|node| node.children.is_empty() && node.name == name)
+    {
+        nodes.remove(index);
+        return true;
+    }
+
+    for index in 0..nodes.len() {
+        if remove_leaf(&mut nodes[index].children, name) {
+            if nodes[index].children.is_empty() {
+                nodes.remove(index);
+            }
+            return true;
+        }
+    }
+
+    false
+}
+
+/// Renames the fund with the given name. Returns whether the fund was found
+pub fn rename_leaf(nodes: &mut [TargetNode], name: &str, new_name: &str) -> bool {
+    nodes.iter_mut().any(|node| {
+        if node.children.is_empty() && node.name == name {
+            node.name = new_name.to_string();
+            true
+        } else {
+            rename_leaf(&mut node.children, name, new_name)
+        }
+    })
+}
+
+fn grouping_target() -> Target {
+    Target {
+        allocation: Percent(hundred()),
+        absolute_band: None,
+        relative_band: None,
+        minimum_trade: None,
+    }
+}
+
+/// Flattens the tree into the effective target of every fund, e.g. 70% stocks * 60% US *
+/// 100% Domestic Stock ETF gives the ETF a 42% target
+pub fn flatten_targets(tree: &[TargetNode]) -> HashMap<String, Target> {
+    let mut target_map = HashMap::new();
+    flatten_nodes(tree, &hundred(), &mut target_map);
+    target_map
+}
+
+fn hundred() -> BigRational {
+    BigRational::from_integer(BigInt::from(100))
+}
+
+fn flatten_nodes(
+    nodes: &[TargetNode],
+    parent_percent: &BigRational,
+    target_map: &mut HashMap<String, Target>,
+) {
+    for node in nodes {
+        let Percent(allocation) = &node.target.allocation;
+        let percent = parent_percent * allocation / hundred();
+
+        if node.children.is_empty() {
+            target_map.insert(
+                node.name.clone(),
+                Target {
+                    allocation: Percent(percent),
+                    absolute_band: node.target.absolute_band.clone(),
+                    relative_band: node.target.relative_band.clone(),
+                    minimum_trade: node.target.minimum_trade.clone(),
+                },
+            );
+        } else {
+            flatten_nodes(&node.children, &percent, target_map);
+        }
+    }
+}
+
+/// Builds the visible rows of the Targets pane: asset class, target %, actual % and drift in
+/// percentage points at every level of the tree. Each row comes with the index path of its
+/// node so the pane can collapse it
+pub fn to_tree_display(tree: &[TargetNode], portfolio: &[Asset]) -> Vec<(Vec<usize>, Vec<String>)> {
+    let total = portfolio_total(portfolio);
+    let mut rows = vec![];
+    tree_rows(tree, portfolio, &total, &hundred(), &mut vec![], &mut rows);
+    rows
+}
+
+fn tree_rows(
+    nodes: &[TargetNode],
+    portfolio: &[Asset],
+    total: &BigRational,
+    parent_percent: &BigRational,
+    path: &mut Vec<usize>,
+    rows: &mut Vec<(Vec<usize>, Vec<String>)>,
+) {
+    for (index, node) in nodes.iter().enumerate() {
+        let Percent(allocation) = &node.target.allocation;
+        let target_percent = parent_percent * allocation / hundred();
+
+        let leaf_names = node.leaf_names();
+        let value = portfolio
+            .iter()
+            .filter(|asset| leaf_names.contains(&asset.name.as_str()))
+            .fold(BigRational::zero(), |value, asset| value + &asset.value);
+        let actual_percent = if *total > BigRational::zero() {
+            value / total * hundred()
+        } else {
+            BigRational::zero()
+        };
+
+        let marker = match (node.children.is_empty(), node.collapsed) {
+            (true, _) => "  ",
+            (false, true) => "+ ",
+            (false, false) => "- ",
+        };
+
+        path.push(index);
+        rows.push((
+            path.clone(),
+            vec![
+                format!("{}{}{}", "  ".repeat(path.len() - 1), marker, node.name),
+                format!("{}%", format_rational(&target_percent, 2)),
+                format!("{}%", format_rational(&actual_percent, 2)),
+                format_rational(&(&actual_percent - &target_percent), 2),
+            ],
+        ));
+
+        if !node.collapsed {
+            tree_rows(
+                &node.children,
+                portfolio,
+                total,
+                &target_percent,
+                path,
+                rows,
+            );
+        }
+        path.pop();
+    }
+}
+
+/// Finds the node at the index path of a Targets pane row
+pub fn node_at<'a>(tree: &'a [TargetNode], path: &[usize]) -> Option<&'a TargetNode> {
+    let (first, rest) = path.split_first()?;
+    let node = tree.get(*first)?;
+
+    if rest.is_empty() {
+        Some(node)
+    } else {
+        node_at(&node.children, rest)
+    }
+}
+
+/// Finds the node at the index path of a Targets pane row, for changing it
+pub fn node_at_mut<'a>(tree: &'a mut [TargetNode], path: &[usize]) -> Option<&'a mut TargetNode> {
+    let (first, rest) = path.split_first()?;
+    let node = tree.get_mut(*first)?;
+
+    if rest.is_empty() {
+        Some(node)
+    } else {
+        node_at_mut(&mut node.children, rest)
+    }
+}
+
+/// Finds the first group of sibling targets that doesn't add up to 100%, e.g. "Stocks/US adds
+/// up to 95%". `pending` is an allocation being typed in for the node at that index path, it
+/// is used in place of the node's current allocation
+pub fn unbalanced_group(
+    tree: &[TargetNode],
+    pending: Option<(&[usize], &BigRational)>,
+) -> Option<String> {
+    unbalanced_nodes(tree, pending, &mut vec![], &mut vec![])
+}
+
+fn unbalanced_nodes(
+    nodes: &[TargetNode],
+    pending: Option<(&[usize], &BigRational)>,
+    path: &mut Vec<usize>,
+    names: &mut Vec<String>,
+) -> Option<String> {
+    if let Some(problem) = group_problem(nodes, pending, path, names) {
+        return Some(problem);
+    }
+
+    for (index, node) in nodes.iter().enumerate() {
+        path.push(index);
+        names.push(node.name.clone());
+        let problem = unbalanced_nodes(&node.children, pending, path, names);
+        names.pop();
+        path.pop();
+
+        if problem.is_some() {
+            return problem;
+        }
+    }
+
+    None
+}
+
+/// Every group of sibling targets that doesn't add up to 100%, at every level of the tree, in
+/// the same wording as `unbalanced_group`
+pub fn unbalanced_groups(tree: &[TargetNode]) -> Vec<String> {
+    let mut problems = vec![];
+    collect_unbalanced(tree, &mut vec![], &mut vec![], &mut problems);
+    problems
+}
+
+fn collect_unbalanced(
+    nodes: &[TargetNode],
+    path: &mut Vec<usize>,
+    names: &mut Vec<String>,
+    problems: &mut Vec<String>,
+) {
+    problems.extend(group_problem(nodes, None, path, names));
+
+    for (index, node) in nodes.iter().enumerate() {
+        path.push(index);
+        names.push(node.name.clone());
+        collect_unbalanced(&node.children, path, names, problems);
+        names.pop();
+        path.pop();
+    }
+}
+
+/// Checks that the sibling `nodes` of the group at `path` add up to 100%, without looking at
+/// their children
+fn group_problem(
+    nodes: &[TargetNode],
+    pending: Option<(&[usize], &BigRational)>,
+    path: &mut Vec<usize>,
+    names: &[String],
+) -> Option<String> {
+    if nodes.is_empty() {
+        return None;
+    }
+
+    let mut sum = BigRational::zero();
+    for (index, node) in nodes.iter().enumerate() {
+        path.push(index);
+        sum += match pending {
+            Some((pending_path, allocation)) if pending_path == path.as_slice() => allocation,
+            _ => &node.target.allocation.0,
+        };
+        path.pop();
+    }
+
+    if sum == hundred() {
+        return None;
+    }
+
+    let group = if names.is_empty() {
+        "Top level targets".to_string()
+    } else {
+        names.join("/")
+    };
+    Some(format!("{} adds up to {}%", group, format_trimmed(&sum, 4)))
+}
+
+/// Writes the target tree back to the targets file. Rows of the file keep their place and any
+/// columns after the minimum trade, rows left out of the tree when it was read (0% targets and
+/// their children) are kept as they are, rows of removed or renamed funds are dropped and nodes
+/// without a row are added at the end. Comments, blank lines and column padding are kept
+pub fn save_targets(path_to_targets: &str, tree: &[TargetNode]) -> Result<(), Box<dyn Error>> {
+    let existing = read_lines(path_to_targets)?;
+
+    let record_path = |record: &csv::StringRecord| -> Vec<String> {
+        record
+            .get(0)
+            .unwrap_or("")
+            .split('/')
+            .map(|segment| segment.trim().to_string())
+            .collect()
+    };
+    let zero_rows: Vec<Vec<String>> = existing
+        .iter()
+        .filter_map(|line| match line {
+            CsvLine::Row(record) => Some(record),
+            CsvLine::Text(_) => None,
+        })
+        .filter(|record| {
+            record
+                .get(1)
+                .and_then(parse_decimal)
+                .is_some_and(|allocation| allocation <= BigRational::zero())
+        })
+        .map(record_path)
+        .collect();
+
+    let mut nodes = vec![];
+    collect_nodes(tree, &mut vec![], &mut nodes);
+
+    let mut lines = vec![];
+    let mut written: Vec<Vec<String>> = vec![];
+
+    for line in &existing {
+        let record = match line {
+            CsvLine::Text(text) => {
+                lines.push(text.clone());
+                continue;
+            }
+            CsvLine::Row(record) => record,
+        };
+        let path = record_path(record);
+
+        match nodes.iter().find(|(node_path, _)| *node_path == path) {
+            Some((_, node)) => {
+                let mut row = target_row(record.get(0).unwrap(), &node.target);
+                if record.len() > 5 {
+                    row.resize(5, String::new());
+                    row.extend(record.iter().skip(5).map(str::to_string));
+                }
+                lines.push(to_line(&pad_like(record, row))?);
+                written.push(path);
+            }
+            None if zero_rows.iter().any(|prefix| path.starts_with(prefix)) => lines.push(to_line(
+                &record.iter().map(str::to_string).collect::<Vec<_>>(),
+            )?),
+            None => {}
+        }
+    }
+
+    for (path, node) in &nodes {
+        if written.contains(path) || node.is_plain_grouping() {
+            continue;
+        }
+        lines.push(to_line(&target_row(&path.join("/"), &node.target))?);
+    }
+
+    save_lines(path_to_targets, &lines)
+}
+
+/// Every node of the tree along with its path of names
+fn collect_nodes<'a>(
+    nodes: &'a [TargetNode],
+    names: &mut Vec<String>,
+    collected: &mut Vec<(Vec<String>, &'a TargetNode)>,
+) {
+    for node in nodes {
+        names.push(node.name.clone());
+        collected.push((names.clone(), node));
+        collect_nodes(&node.children, names, collected);
+        names.pop();
+    }
+}
+
+/// The name, allocation and optional band and minimum trade columns of a targets file row,
+/// trailing blank columns are left off
+fn target_row(name: &str, target: &Target) -> Vec<String> {
+    let optional =
+        |value: Option<&BigRational>| value.map_or(String::new(), |value| format_trimmed(value, 6));
+
+    let mut row = vec![
+        name.to_string(),
+        format_trimmed(&target.allocation.0, 6),
+        optional(target.absolute_band.as_ref().map(|band| &band.0)),
+        optional(target.relative_band.as_ref().map(|band| &band.0)),
+        optional(target.minimum_trade.as_ref()),
+    ];
+
+    while row.last().is_some_and(|column| column.is_empty()) {
+        row.pop();
+    }
+
+    row
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, fs};
+
+    fn target(percent: &str) -> Target {
+        Target {
+            allocation: Percent(parse_decimal(percent).unwrap()),
+            absolute_band: None,
+            relative_band: None,
+            minimum_trade: None,
+        }
+    }
+
+    /// builds a tree from `path, percent` rows the way the targets file is read
+    fn tree(rows: &[(&str, &str)]) -> Vec<TargetNode> {
+        let mut tree = vec![];
+        for (path, percent) in rows {
+            let path: Vec<String> = path.split('/').map(str::to_string).collect();
+            insert_node(&mut tree, &path, target(percent));
+        }
+        tree
+    }
+
+    #[test]
+    fn every_unbalanced_group_is_reported() {
+        let tree = tree(&[
+            ("Stocks", "60"),
+            ("Stocks/US/Domestic Stock ETF", "90"),
+            ("Stocks/International Stock ETF", "20"),
+            ("Bonds", "40"),
+            ("Bonds/Bond fund", "100"),
+        ]);
+
+        assert_eq!(
+            unbalanced_groups(&tree),
+            vec!["Stocks adds up to 120%", "Stocks/US adds up to 90%"]
+        );
+        assert_eq!(
+            unbalanced_group(&tree, None),
+            Some("Stocks adds up to 120%".to_string())
+        );
+    }
+
+    #[test]
+    fn flattening_multiplies_the_shares_down_the_tree() {
+        let tree = tree(&[
+            ("Stocks", "60"),
+            ("Stocks/US", "50"),
+            ("Stocks/US/Domestic Stock ETF", "100"),
+            ("Stocks/International Stock ETF", "50"),
+            ("Bonds", "40"),
+            ("Bonds/Bond fund", "100"),
+        ]);
+
+        let target_map = flatten_targets(&tree);
+
+        let allocation = |name: &str| target_map[name].allocation.0.clone();
+        assert_eq!(target_map.len(), 3);
+        assert_eq!(
+            allocation("Domestic Stock ETF"),
+            parse_decimal("30").unwrap()
+        );
+        assert_eq!(
+            allocation("International Stock ETF"),
+            parse_decimal("30").unwrap()
+        );
+        assert_eq!(allocation("Bond fund"), parse_decimal("40").unwrap());
+    }
+
+    #[test]
+    fn a_fund_listed_under_two_asset_classes_is_a_load_error() {
+        let path = env::temp_dir()
+            .join("tui-rebalance-duplicate-targets.csv")
+            .to_string_lossy()
+            .to_string();
+        fs::write(
+            &path,
+            "Stocks, 60\nStocks/Total Market ETF, 100\nBonds, 40\nBonds/Total Market ETF, 100\n",
+        )
+        .unwrap();
+
+        let errors = create_target_tree(&path).err().unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0]
+            .to_string()
+            .ends_with(":4: Total Market ETF is already listed as Stocks/Total Market ETF"));
+    }
+}
@@ -1,63 +1,38 @@
-use super::rebalance::format_f64;
-use super::rebalance::to_f64;
-use super::rebalance::{lazy_rebalance, to_vec_display};
-use crate::util::rebalance::Asset;
-use num::BigRational;
+use chrono::Local;
+use num::{BigRational, One, Zero};
 use std::{collections::HashMap, error::Error};
-use tui::widgets::{ListState, TableState};
-
-pub struct StatefulList<T> {
-    pub state: ListState,
-    pub items: Vec<T>,
-}
-
-impl<T> StatefulList<T> {
-    pub fn new() -> StatefulList<T> {
-        StatefulList {
-            state: ListState::default(),
-            items: Vec::new(),
-        }
-    }
-
-    pub fn with_items(items: Vec<T>) -> StatefulList<T> {
-        StatefulList {
-            state: ListState::default(),
-            items,
-        }
-    }
-
-    pub fn next(&mut self) {
-        let i = match self.state.selected() {
-            Some(i) => {
-                if i >= self.items.len() - 1 {
-                    0
-                } else {
-                    i + 1
-                }
-            }
-            None => 0,
-        };
-        self.state.select(Some(i));
-    }
-
-    pub fn previous(&mut self) {
-        let i = match self.state.selected() {
-            Some(i) => {
-                if i == 0 {
-                    self.items.len() - 1
-                } else {
-                    i - 1
-                }
-            }
-            None => 0,
-        };
-        self.state.select(Some(i));
-    }
-
-    pub fn unselect(&mut self) {
-        self.state.select(None);
-    }
-}
+use tui::widgets::TableState;
+use tui_rebalance::backtest::{
+    backtest_summary, run_backtest, to_backtest_rows, Backtest, BacktestResult, PriceSeries,
+};
+use tui_rebalance::config::PortfolioFiles;
+use tui_rebalance::error::LoadError;
+use tui_rebalance::journal::{
+    append_entry, diff_rows, entry_rows, read_journal, to_history_rows, JournalEntry,
+};
+use tui_rebalance::portfolio::{
+    adjust_target_allocation_percent, consolidate, create_asset, save_holdings, Account, Percent,
+    Portfolio, Target,
+};
+use tui_rebalance::projection::{
+    projection_summary, simulate, Assumption, Projection, ProjectionResult, Simulation,
+};
+use tui_rebalance::rebalance::parse_decimal;
+use tui_rebalance::rebalance::{
+    band_rebalance, full_account_rebalance, full_rebalance, lazy_account_rebalance,
+    lazy_rebalance_with_minimum, portfolio_total, reverse_plan, round_to_whole_shares,
+    to_vec_display, withdraw, Asset, AssetPlan, BandTarget, RebalanceMode, RebalancePlan,
+};
+use tui_rebalance::rebalance::{
+    drift_metrics, drift_metrics_after, format_rational, format_trimmed, to_f64,
+};
+use tui_rebalance::schedule::{
+    plan_schedule, schedule_summary, schedule_to_csv, to_schedule_rows, Schedule, SchedulePeriod,
+};
+use tui_rebalance::targets::{
+    flatten_targets, insert_node, node_at, node_at_mut, remove_leaf, rename_leaf, save_targets,
+    to_tree_display, unbalanced_group, TargetNode,
+};
 
 pub struct StatefulTable {
     pub state: TableState,
@@ -72,6 +47,9 @@ impl StatefulTable {
         }
     }
     pub fn next(&mut self) {
+        if self.items.is_empty() {
+            return;
+        }
         let i = match self.state.selected() {
             Some(i) => {
                 if i >= self.items.len() - 1 {
@@ -86,6 +64,9 @@ impl StatefulTable {
     }
 
     pub fn previous(&mut self) {
+        if self.items.is_empty() {
+            return;
+        }
         let i = match self.state.selected() {
             Some(i) => {
                 if i == 0 {
@@ -100,101 +81,612 @@ impl StatefulTable {
     }
 }
 
-/// Represent the four modes of operation for the GUI
+/// Represent the five modes of operation for the GUI
 /// Normal just for viewing the portfolio data
 /// Editing is for editing the asset values
 /// Exec is for entering an amount to rebalance
+/// Withdraw is for entering an amount of cash to raise by selling
 /// ErrorDisplay is for highlighting that input validation has failed
 pub enum InputMode {
     Normal,
     Editing,
     Exec,
+    Withdraw,
+    /// reviewing the trades of a rebalance or withdrawal before executing them
+    Preview,
+    /// typing the name, target % and value of a new asset
+    Insert,
+    /// typing the new name of the selected asset
+    Rename,
+    /// confirming the removal of the selected asset
+    Delete,
+    /// typing the amount, frequency and months of a contribution schedule
+    Schedule,
+    /// typing the contribution, frequency and calendar months of a backtest
+    Backtest,
+    /// typing the contribution, frequency, years and goal of a projection
+    Projection,
     ErrorDisplay,
 }
 
+/// The table the arrow keys and 'e' act on, Tab switches between them
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Focus {
+    Portfolio,
+    Targets,
+    History,
+    /// only reachable once a contribution schedule was planned
+    Schedule,
+    /// only reachable once a backtest was run
+    Backtest,
+    /// only reachable once a projection was simulated
+    Projection,
+}
+
+/// The portfolio and targets as they were before a change, kept so the change can be undone
+struct Snapshot {
+    portfolio: Vec<Asset>,
+    /// the holdings of each account, in the order of `App::accounts`
+    holdings: Vec<Vec<Asset>>,
+    target_tree: Vec<TargetNode>,
+    /// the trades that led from this state to the next one, or back, when they were executed
+    executed: Option<ExecutedTrades>,
+}
+
+/// Executed trades as undo and redo need them to journal taking them back or making them again
+#[derive(Clone)]
+struct ExecutedTrades {
+    action: String,
+    account: Option<String>,
+    plan: RebalancePlan,
+}
+
 /// This struct holds the current state of the app including tracking three UI input odes
 /// and keeping state of the portfolio struct
-pub struct App<'a> {
-    pub items: StatefulList<(&'a str, usize)>,
+pub struct App {
     pub table_portfolio: StatefulTable,
     pub table_targets: StatefulTable,
     pub table_results: StatefulTable,
+    /// one row per journal entry, shown in place of the results while it has the focus
+    pub table_history: StatefulTable,
+    /// one row per purchase of the planned schedule, shown in place of the results while it
+    /// has the focus
+    pub table_schedule: StatefulTable,
+    /// one row per strategy of the last backtest, shown below its chart
+    pub table_backtest: StatefulTable,
     pub events: Vec<(String, String)>,
     pub input_mode: InputMode,
     /// input entered by the user
     pub input: String,
+    /// the household portfolio consolidated across every account, this is what gets rebalanced
     pub portfolio: Vec<Asset>,
+    pub accounts: Vec<Account>,
+    /// assets deleted or renamed this session, saves drop their rows from the portfolio files
+    /// while rows of assets without a target are kept as they are
+    removed_assets: Vec<String>,
+    /// the account shown in the Portfolio pane and receiving the contribution, None shows the
+    /// consolidated household view
+    pub selected_account: Option<usize>,
+    pub target_map: HashMap<String, Target>,
+    /// the targets as the asset class tree shown in the Targets pane
+    pub target_tree: Vec<TargetNode>,
+    /// edited targets are saved back here
+    pub path_to_targets: String,
+    pub focus: Focus,
+    /// shows the allocation bar chart in place of the Targets pane
+    pub show_chart: bool,
+    /// index path in `target_tree` of each row of the Targets pane
+    pub target_paths: Vec<Vec<usize>>,
     /// rebalance amount
-    pub contribution_amount: f64,
+    pub contribution_amount: BigRational,
+    /// how the rebalance amount is spread across the assets
+    pub rebalance_mode: RebalanceMode,
+    /// where a band rebalance moves the out of band assets to
+    pub band_target: BandTarget,
+    /// lazy rebalance trades below this amount are dropped unless the asset sets its own
+    pub minimum_trade: Option<BigRational>,
     /// the error message to display if validation fails
     pub error_msg: String,
-    pub path_to_portfolio: String,
+    /// every problem found in the targets and portfolio files, nothing is loaded when there are any
+    pub load_errors: Vec<LoadError>,
+    /// column of the portfolio files holding the asset value, saves write it back there
+    pub portfolio_value_index: usize,
+    /// title of the Rebalance pane describing the results it shows
+    pub results_title: String,
+    /// cash the last rebalance couldn't spend, None when there is nothing worth reporting
+    pub unspent_cash: Option<BigRational>,
+    /// the trades of the last rebalance or withdrawal until they are executed or discarded,
+    /// one per asset of `portfolio` in the same order
+    pub plan: Option<RebalancePlan>,
+    /// executed rebalances are appended here
+    path_to_journal: String,
+    /// every executed rebalance, oldest first
+    pub journal: Vec<JournalEntry>,
+    /// journal lines that couldn't be read and were skipped, they never stop the app from trading
+    pub journal_warnings: Vec<LoadError>,
+    /// journal entry the History pane compares the selected entry with
+    pub history_mark: Option<usize>,
+    /// state before each change of this session, the latest last
+    undo_stack: Vec<Snapshot>,
+    /// changes undone since the last new change, the latest undo last
+    redo_stack: Vec<Snapshot>,
+    /// expected annual return of each asset as a fraction, schedules keep the prices of the
+    /// other assets flat
+    pub expected_returns: HashMap<String, BigRational>,
+    /// the contribution schedule shown in the Schedule pane
+    pub schedule: Option<Schedule>,
+    schedule_periods: Vec<SchedulePeriod>,
+    /// totals and drift of the planned schedule shown below its purchases
+    pub schedule_summary: Vec<String>,
+    /// the planned schedule is exported here
+    path_to_schedule: String,
+    /// price history of each asset, backtests replay it
+    pub prices: HashMap<String, PriceSeries>,
+    /// the backtest shown in the Backtest pane
+    pub backtest: Option<Backtest>,
+    backtest_results: Vec<BacktestResult>,
+    /// expected return, volatility and correlations of each asset, projections simulate them
+    pub assumptions: Vec<Assumption>,
+    /// how many paths a projection simulates and their seed
+    pub simulation: Simulation,
+    /// the projection shown in the Projection pane
+    pub projection: Option<Projection>,
+    projection_result: Option<ProjectionResult>,
+    /// chance of reaching the goal and the totals of the projection shown below its chart
+    pub projection_summary: Vec<String>,
 }
 
-impl<'a> App<'a> {
-    pub fn new() -> App<'a> {
-        let mut table_portfolio = StatefulTable::new();
-        let mut table_targets = StatefulTable::new();
+impl App {
+    /// Loads the portfolio, either from a single portfolio file or from every account listed
+    /// in the accounts file. When the files have problems the app starts out empty with the
+    /// problems listed in `load_errors`
+    pub fn new(files: &PortfolioFiles) -> App {
+        let table_portfolio = StatefulTable::new();
+        let table_targets = StatefulTable::new();
         let table_results = StatefulTable::new();
 
-        //default path should be moved to an argument or config file
-        let path_to_targets = "example/targets.csv";
-        let path_to_portfolio = "example/portfolio.csv";
-        let _contribution_amount = 10000.00;
-        let portfolio_value_index = 1;
+        let table_history = StatefulTable::new();
+        let table_schedule = StatefulTable::new();
+        let table_backtest = StatefulTable::new();
 
-        let target_map = create_target_map(path_to_targets);
-        let portfolio = create_portfolio(path_to_portfolio, portfolio_value_index, &target_map);
+        let (target_tree, accounts, load_errors) = match Portfolio::load(files) {
+            Ok(portfolio) => (portfolio.target_tree, portfolio.accounts, vec![]),
+            Err(errors) => (vec![], vec![], errors),
+        };
+        let (journal, journal_warnings) = read_journal(&files.journal());
+        let target_map = flatten_targets(&target_tree);
+        let portfolio = consolidate(&accounts, &target_map);
 
         /*let display_target: Vec<Vec<String>> = target_map
         .into_iter()
         .map(|(symbol, percent)| vec![symbol, format!("{}%", percent.0)])
         .collect();*/
 
-        let display_vecs: Vec<(Vec<String>, Vec<String>)> = portfolio
-            .iter()
-            .map(|asset| {
-                (
-                    vec![
-                        asset.name.clone(),
-                        format!("${}", format_f64(to_f64(&asset.value), 2)),
-                    ],
-                    vec![
-                        asset.name.to_string(),
-                        format!(
-                            "{}%",
-                            format_f64(
-                                to_f64(
-                                    &(asset.target_allocation_percent.clone()
-                                        * BigRational::from_float(100.00).unwrap())
-                                ),
-                                2
-                            )
-                        ),
-                    ],
-                )
-            })
-            .collect();
-
-        let (display_portfolio, display_target): (Vec<_>, Vec<_>) =
-            display_vecs.iter().cloned().unzip();
-
-        table_targets.items = display_target;
-        table_portfolio.items = display_portfolio;
-
         let internal_events = Vec::<(String, String)>::new();
-        App {
-            items: StatefulList::with_items(vec![]),
+        let mut app = App {
             table_portfolio,
             table_targets,
             table_results,
+            table_history,
+            table_schedule,
+            table_backtest,
             events: internal_events,
             input_mode: InputMode::Normal,
             input: String::new(),
             portfolio,
-            contribution_amount: 0.0,
+            accounts,
+            removed_assets: vec![],
+            selected_account: None,
+            target_map,
+            target_tree,
+            path_to_targets: files.targets.clone(),
+            focus: Focus::Portfolio,
+            show_chart: false,
+            target_paths: vec![],
+            contribution_amount: BigRational::zero(),
+            rebalance_mode: RebalanceMode::Lazy,
+            band_target: BandTarget::Target,
+            minimum_trade: None,
             error_msg: String::new(),
-            path_to_portfolio: path_to_portfolio.to_string(),
+            load_errors,
+            portfolio_value_index: files.value_index,
+            results_title: "Rebalance".to_string(),
+            unspent_cash: None,
+            plan: None,
+            path_to_journal: files.journal(),
+            journal,
+            journal_warnings,
+            history_mark: None,
+            undo_stack: vec![],
+            redo_stack: vec![],
+            expected_returns: HashMap::new(),
+            schedule: None,
+            schedule_periods: vec![],
+            schedule_summary: vec![],
+            path_to_schedule: files.schedule(),
+            prices: HashMap::new(),
+            backtest: None,
+            backtest_results: vec![],
+            assumptions: vec![],
+            simulation: Simulation::default(),
+            projection: None,
+            projection_result: None,
+            projection_summary: vec![],
+        };
+        app.refresh_portfolio_table();
+        app.refresh_targets_table();
+        app.refresh_history_table();
+        app
+    }
+
+    /// Rebuilds the Targets pane rows from the target tree and the current holdings
+    pub fn refresh_targets_table(&mut self) {
+        let (paths, rows) = to_tree_display(&self.target_tree, &self.portfolio)
+            .into_iter()
+            .unzip();
+        self.target_paths = paths;
+        self.table_targets.items = rows;
+    }
+
+    /// Collapses or expands the asset class selected in the Targets pane
+    pub fn toggle_target(&mut self) {
+        let path = match self.table_targets.state.selected() {
+            Some(index) => self.target_paths[index].clone(),
+            None => return,
+        };
+
+        if let Some(node) = node_at_mut(&mut self.target_tree, &path) {
+            node.collapsed = !node.collapsed;
+        }
+
+        self.refresh_targets_table();
+        // keep the cursor on the toggled row
+        let index = self.target_paths.iter().position(|row| *row == path);
+        self.table_targets.state.select(index);
+    }
+
+    /// Moves the focus to the next table, the History, Schedule, Backtest and Projection panes
+    /// take the place of the results
+    pub fn toggle_focus(&mut self) {
+        self.focus = match self.focus {
+            Focus::Portfolio if self.show_chart => Focus::History,
+            Focus::Portfolio => Focus::Targets,
+            Focus::Targets => Focus::History,
+            Focus::History if self.schedule.is_some() => Focus::Schedule,
+            Focus::History | Focus::Schedule if self.backtest.is_some() => Focus::Backtest,
+            Focus::History | Focus::Schedule | Focus::Backtest if self.projection.is_some() => {
+                Focus::Projection
+            }
+            Focus::History | Focus::Schedule | Focus::Backtest | Focus::Projection => {
+                Focus::Portfolio
+            }
+        };
+    }
+
+    /// Rebuilds the History pane rows, the entry marked for comparison is starred
+    pub fn refresh_history_table(&mut self) {
+        let mut rows = to_history_rows(&self.journal);
+        if let Some(row) = self.history_mark.and_then(|index| rows.get_mut(index)) {
+            row[0].push('*');
+        }
+
+        self.table_history.items = rows;
+    }
+
+    /// Marks the selected journal entry to compare the other entries with, or clears the mark
+    /// when it is already marked
+    pub fn toggle_history_mark(&mut self) {
+        let selected = self.table_history.state.selected();
+        self.history_mark = if self.history_mark == selected {
+            None
+        } else {
+            selected
+        };
+
+        self.refresh_history_table();
+    }
+
+    /// Title, header and rows of the journal details below the History pane: the trades of the
+    /// selected entry, or how the portfolio changed since the marked entry
+    pub fn history_detail(&self) -> (String, Vec<String>, Vec<Vec<String>>) {
+        let to_strings = |header: &[&str]| header.iter().map(|text| text.to_string()).collect();
+
+        match (self.history_mark, self.table_history.state.selected()) {
+            (Some(from), Some(to)) if from != to => (
+                format!("Changes from #{} to #{}", from + 1, to + 1),
+                vec![
+                    "Asset".to_string(),
+                    format!("#{} %", from + 1),
+                    format!("#{} %", to + 1),
+                    "Change".to_string(),
+                    format!("#{} value", from + 1),
+                    format!("#{} value", to + 1),
+                ],
+                diff_rows(&self.journal[from], &self.journal[to]),
+            ),
+            (_, Some(index)) => (
+                format!("Entry #{}", index + 1),
+                to_strings(&["Asset", "Value before", "Trade", "Allocation after %"]),
+                entry_rows(&self.journal[index]),
+            ),
+            (_, None) => ("Select an entry".to_string(), vec![], vec![]),
+        }
+    }
+
+    /// The percentage of the selected Targets pane row, as it would be typed in
+    pub fn selected_target_percent(&self) -> Option<String> {
+        let index = self.table_targets.state.selected()?;
+        let node = node_at(&self.target_tree, &self.target_paths[index])?;
+
+        Some(format_trimmed(&node.target.allocation.0, 6))
+    }
+
+    /// Sets the percentage of the asset class on the given Targets pane row. The percentage is
+    /// a share of the parent row. The targets file is saved once every group adds up to 100%
+    pub fn update_target(&mut self, index: usize, new_percent: String) -> Result<(), String> {
+        self.check_loaded()?;
+
+        let allocation = parse_percent(&new_percent)?;
+        self.push_undo();
+
+        let path = self.target_paths[index].clone();
+        if let Some(node) = node_at_mut(&mut self.target_tree, &path) {
+            node.target.allocation = Percent(allocation);
+        }
+
+        self.apply_targets();
+        self.refresh_targets_table();
+
+        self.save_if_balanced()
+    }
+
+    /// Saves the targets and every account together once the targets add up to 100%, until
+    /// then changes are only kept in memory so the two files never disagree
+    fn save_if_balanced(&mut self) -> Result<(), String> {
+        if unbalanced_group(&self.target_tree, None).is_some() {
+            return Ok(());
+        }
+
+        save_targets(&self.path_to_targets, &self.target_tree)
+            .map_err(|error| format!("Error saving targets: {}", error))?;
+        self.save_portfolio()
+            .map_err(|error| format!("Error saving portfolio: {}", error))
+    }
+
+    /// The name of the asset selected in the Portfolio pane
+    pub fn selected_asset(&self) -> Option<String> {
+        let index = self.table_portfolio.state.selected()?;
+        Some(self.table_portfolio.items[index][0].clone())
+    }
+
+    /// Adds a new asset from input such as `Gold fund, 5, 1000.00`: its name, its target
+    /// percentage and its current value in the active account. The name can be a '/' separated
+    /// path such as `Stocks/US/Small Cap ETF` to place it in the asset class tree, the
+    /// percentage is then a share of the parent row. Like an edited target it must be above 0%
+    pub fn insert_asset(&mut self, input: String) -> Result<(), String> {
+        self.check_loaded()?;
+
+        let mut columns = input.splitn(3, ',').map(str::trim);
+        let (path, percent, value) = match (columns.next(), columns.next(), columns.next()) {
+            (Some(path), Some(percent), Some(value)) => (path, percent, value),
+            _ => return Err("Enter the name, target % and value separated by commas".to_string()),
+        };
+
+        let path: Vec<String> = path
+            .split('/')
+            .map(|segment| segment.trim().to_string())
+            .collect();
+        if path.iter().any(String::is_empty) {
+            return Err("The asset needs a name".to_string());
+        }
+        let (name, parents) = path.split_last().unwrap();
+        if self.target_map.contains_key(name) {
+            return Err(format!("{} already has a target", name));
+        }
+        if let Some(parent) = parents
+            .iter()
+            .find(|parent| self.target_map.contains_key(*parent))
+        {
+            return Err(format!("{} is a fund, not an asset class", parent));
+        }
+
+        let allocation = parse_percent(percent)?;
+        let value =
+            parse_decimal(value).ok_or_else(|| format!("{} is not a dollar amount", value))?;
+        let account = self.active_account()?;
+        self.push_undo();
+
+        let target = Target {
+            allocation: Percent(allocation),
+            absolute_band: None,
+            relative_band: None,
+            minimum_trade: None,
+        };
+        insert_node(&mut self.target_tree, &path, target);
+        self.apply_targets();
+
+        let asset = create_asset(name.clone(), &self.target_map[name], value);
+        self.accounts[account].holdings.push(asset.clone());
+        self.portfolio.push(asset);
+
+        self.refresh_portfolio_table();
+        self.refresh_targets_table();
+
+        self.save_if_balanced()
+    }
+
+    /// Removes the asset on the given Portfolio pane row from the targets and from every account
+    pub fn delete_asset(&mut self, index: usize) -> Result<(), String> {
+        self.check_loaded()?;
+
+        let name = self.table_portfolio.items[index][0].clone();
+        self.push_undo();
+
+        remove_leaf(&mut self.target_tree, &name);
+        self.removed_assets.push(name.clone());
+        self.portfolio.retain(|asset| asset.name != name);
+        for account in self.accounts.iter_mut() {
+            account.holdings.retain(|asset| asset.name != name);
+        }
+        self.apply_targets();
+
+        self.refresh_portfolio_table();
+        self.refresh_targets_table();
+        self.table_targets.state.select(None);
+
+        self.save_if_balanced()
+    }
+
+    /// Renames the asset on the given Portfolio pane row in the targets and in every account
+    pub fn rename_asset(&mut self, index: usize, new_name: String) -> Result<(), String> {
+        self.check_loaded()?;
+
+        let new_name = new_name.trim().to_string();
+        if new_name.is_empty() || new_name.contains('/') || new_name.contains(',') {
+            return Err("The new name can't be empty or contain '/' or ','".to_string());
+        }
+        if self.target_map.contains_key(&new_name) {
+            return Err(format!("{} already has a target", new_name));
+        }
+
+        let name = self.table_portfolio.items[index][0].clone();
+        self.push_undo();
+
+        rename_leaf(&mut self.target_tree, &name, &new_name);
+        self.removed_assets.push(name.clone());
+        let holdings = self
+            .accounts
+            .iter_mut()
+            .flat_map(|account| account.holdings.iter_mut());
+        for asset in self.portfolio.iter_mut().chain(holdings) {
+            if asset.name == name {
+                asset.name = new_name.clone();
+            }
+        }
+        self.apply_targets();
+
+        self.refresh_portfolio_table();
+        self.refresh_targets_table();
+        self.table_portfolio.state.select(Some(index));
+
+        self.save_if_balanced()
+    }
+
+    /// The first group of targets that doesn't add up to 100%, counting the percentage being
+    /// typed in for the selected Targets pane row
+    pub fn targets_problem(&self) -> Option<String> {
+        let pending = match (&self.input_mode, self.focus) {
+            (InputMode::Editing, Focus::Targets) => self
+                .table_targets
+                .state
+                .selected()
+                .zip(parse_decimal(&self.input))
+                .map(|(index, percent)| (self.target_paths[index].as_slice(), percent)),
+            _ => None,
+        };
+
+        unbalanced_group(
+            &self.target_tree,
+            pending.as_ref().map(|(path, percent)| (*path, percent)),
+        )
+    }
+
+    /// Gives every asset its target from the current target tree
+    fn apply_targets(&mut self) {
+        self.target_map = flatten_targets(&self.target_tree);
+
+        let holdings = self
+            .accounts
+            .iter_mut()
+            .flat_map(|account| account.holdings.iter_mut());
+        for asset in self.portfolio.iter_mut().chain(holdings) {
+            if let Some(target) = self.target_map.get(&asset.name) {
+                asset.target_allocation_percent =
+                    adjust_target_allocation_percent(&target.allocation.0);
+            }
+        }
+    }
+
+    /// Rebuilds the Portfolio pane rows for the consolidated or the selected account view
+    pub fn refresh_portfolio_table(&mut self) {
+        let assets = match self.selected_account {
+            Some(index) => &self.accounts[index].holdings,
+            None => &self.portfolio,
+        };
+
+        self.table_portfolio.items = assets
+            .iter()
+            .map(|asset| {
+                vec![
+                    asset.name.clone(),
+                    format!("${}", format_rational(&asset.value, 2)),
+                ]
+            })
+            .collect();
+        self.table_portfolio.state.select(None);
+    }
+
+    /// Cycles the Portfolio pane from the consolidated view through each account
+    pub fn next_account(&mut self) {
+        if self.accounts.len() < 2 {
+            return;
+        }
+
+        self.selected_account = match self.selected_account {
+            None => Some(0),
+            Some(index) if index + 1 < self.accounts.len() => Some(index + 1),
+            Some(_) => None,
+        };
+        self.refresh_portfolio_table();
+    }
+
+    /// Title of the Portfolio pane naming the account being shown
+    pub fn portfolio_title(&self) -> String {
+        match self.selected_account {
+            _ if self.accounts.len() < 2 => "Portfolio".to_string(),
+            Some(index) => format!("Portfolio ({})", self.accounts[index].name),
+            None => "Portfolio (Household)".to_string(),
+        }
+    }
+
+    /// Shows the named account in the Portfolio pane and makes it receive the contribution
+    pub fn select_account(&mut self, name: &str) -> Result<(), String> {
+        let index = self
+            .accounts
+            .iter()
+            .position(|account| account.name == name)
+            .ok_or_else(|| format!("There is no account named '{}'", name))?;
+
+        self.selected_account = Some(index);
+        self.refresh_portfolio_table();
+
+        Ok(())
+    }
+
+    /// The account that edits and contributions apply to, the only account when there is
+    /// just one
+    fn active_account(&self) -> Result<usize, String> {
+        match self.selected_account {
+            Some(index) => Ok(index),
+            None if self.accounts.len() == 1 => Ok(0),
+            None => Err("Press 'a' to select an account first".to_string()),
+        }
+    }
+
+    /// Refuses to plan trades for a portfolio that failed to load
+    fn check_loaded(&self) -> Result<(), String> {
+        if self.load_errors.is_empty() {
+            Ok(())
+        } else {
+            Err("Fix the problems in the portfolio files first".to_string())
+        }
+    }
+
+    /// Refuses to plan trades while the targets don't add up to 100%
+    fn check_targets(&self) -> Result<(), String> {
+        match unbalanced_group(&self.target_tree, None) {
+            Some(problem) => Err(format!("{}, targets must add up to 100%", problem)),
+            None => Ok(()),
         }
     }
 
@@ -203,142 +695,636 @@ impl<'a> App<'a> {
         self.events.push((line, "USER".to_string()));
     }
 
-    /// After the UI is updated this makes sure the underlying portfolio struct matches the asset value
-    pub fn update_asset(&mut self, index: usize, new_value: String) {
+    /// Sets the value of the asset on the given Portfolio pane row in the active account and
+    /// keeps the household portfolio in step
+    pub fn update_asset(&mut self, index: usize, new_value: String) -> Result<(), String> {
+        let account = self.active_account()?;
         let row = &self.table_portfolio.items[index];
         let asset_name = row[0].clone();
+        let new_value = parse_decimal(&new_value)
+            .ok_or_else(|| format!("{} is not a dollar amount", new_value))?;
+        self.push_undo();
+
+        let holdings = &mut self.accounts[account].holdings;
+        let old_value = match holdings.iter_mut().find(|asset| asset.name == asset_name) {
+            Some(holding) => {
+                let old_value = holding.value.clone();
+                holding.set_value(new_value.clone());
+                old_value
+            }
+            None => {
+                // the asset only had a target so far, start holding it in this account
+                let target = self.target_map.get(&asset_name).unwrap();
+                holdings.push(create_asset(
+                    asset_name.clone(),
+                    target,
+                    BigRational::zero(),
+                ));
+                holdings.last_mut().unwrap().set_value(new_value.clone());
+                BigRational::zero()
+            }
+        };
+
         if let Some(asset) = self
             .portfolio
             .iter_mut()
             .find(|asset| asset.name == asset_name)
         {
-            let float_parse: f64 = new_value.parse().unwrap();
-            asset.value = BigRational::from_float(float_parse).unwrap();
+            let household_value = &asset.value - old_value + new_value;
+            asset.set_value(household_value);
         }
+
+        self.refresh_portfolio_table();
+        self.refresh_targets_table();
+        self.table_portfolio.state.select(Some(index));
+
+        Ok(())
     }
 
-    /// Save the portfolio to the original CSV file after edits are made
-    pub fn save_portfolio(&mut self /*path_to_portfolio:&str*/) -> Result<(), Box<dyn Error>> {
-        let mut wtr = csv::Writer::from_path(&self.path_to_portfolio)?; //path_to_portfolio)?;
+    /// Applies tolerance bands (given in percent) to every asset the targets file left without one
+    pub fn set_default_bands(
+        &mut self,
+        absolute_band: Option<&BigRational>,
+        relative_band: Option<&BigRational>,
+    ) {
+        for asset in self.portfolio.iter_mut() {
+            if asset.absolute_band.is_none() && asset.relative_band.is_none() {
+                asset.set_bands(
+                    absolute_band.map(adjust_target_allocation_percent),
+                    relative_band.map(adjust_target_allocation_percent),
+                );
+            }
+        }
+    }
 
-        for asset in &self.portfolio {
-            wtr.write_record(&[
-                asset.name.to_string(),
-                format!("${}", format_f64(to_f64(&asset.value), 2)),
-            ])?;
+    /// Switches the Targets pane with the allocation bar chart
+    pub fn toggle_chart(&mut self) {
+        self.show_chart = !self.show_chart;
+        if self.show_chart && self.focus == Focus::Targets {
+            self.focus = Focus::Portfolio;
         }
+    }
 
-        wtr.flush()?;
+    /// The current, target and post-rebalance allocation of each asset in whole percent, and
+    /// whether the asset is out of band. Without a rebalance the post-rebalance allocation is
+    /// the current one
+    pub fn allocation_bars(&self) -> Vec<(String, [u64; 3], bool)> {
+        let total = portfolio_total(&self.portfolio);
+        let deltas: Vec<BigRational> = match self.plan {
+            Some(ref plan) => plan
+                .assets
+                .iter()
+                .map(|trade| trade.delta.clone())
+                .collect(),
+            None => vec![BigRational::zero(); self.portfolio.len()],
+        };
+        let new_total = deltas.iter().fold(total.clone(), |sum, delta| sum + delta);
+        let percent = |value: &BigRational, total: &BigRational| {
+            if total.is_zero() {
+                0
+            } else {
+                (to_f64(&(value / total)) * 100.0).round().max(0.0) as u64
+            }
+        };
+
+        self.portfolio
+            .iter()
+            .zip(&deltas)
+            .map(|(asset, delta)| {
+                let new_value = &asset.value + delta;
+                (
+                    asset.name.clone(),
+                    [
+                        percent(&asset.value, &total),
+                        percent(&asset.target_allocation_percent, &BigRational::one()),
+                        percent(&new_value, &new_total),
+                    ],
+                    asset.is_out_of_band(&total),
+                )
+            })
+            .collect()
+    }
+
+    /// The drift metrics of the portfolio, and once a rebalance has been run of the portfolio
+    /// after its trades
+    pub fn drift_summary(&self) -> Vec<String> {
+        if !self.load_errors.is_empty() {
+            return vec![];
+        }
+
+        let mut lines = vec![format!("Now: {}", drift_metrics(&self.portfolio))];
+        if let Some(ref plan) = self.plan {
+            lines.push(format!(
+                "After the trades: {}",
+                drift_metrics_after(&self.portfolio, plan)
+            ));
+        }
+
+        lines
+    }
+
+    /// Whether the named asset currently sits outside its tolerance band
+    pub fn is_out_of_band(&self, asset_name: &str) -> bool {
+        let total = portfolio_total(&self.portfolio);
+        self.portfolio
+            .iter()
+            .any(|asset| asset.name == asset_name && asset.is_out_of_band(&total))
+    }
+
+    /// Whether the last rebalance dropped the named asset's trade for being under the minimum
+    pub fn is_dropped_trade(&self, asset_name: &str) -> bool {
+        self.planned_trade(asset_name)
+            .is_some_and(|trade| trade.dropped_delta.is_some())
+    }
+
+    /// Save every account to its original CSV file after edits are made
+    pub fn save_portfolio(&mut self) -> Result<(), Box<dyn Error>> {
+        for account in &self.accounts {
+            save_holdings(
+                &account.path_to_portfolio,
+                self.portfolio_value_index,
+                &account.holdings,
+                &self.removed_assets,
+            )?;
+        }
 
         Ok(())
     }
+    /// Executes the rebalance for the selected mode and updates the UI with the results using a helper function
+    /// With several accounts the contribution lands in the selected account and only its holdings trade
+    pub fn rebalance(&mut self) -> Result<(), String> {
+        self.check_loaded()?;
+        self.check_targets()?;
+        if portfolio_total(&self.portfolio) + &self.contribution_amount <= BigRational::zero() {
+            return Err("The portfolio is empty, there is nothing to rebalance".to_string());
+        }
 
-    /// Executes the lazy_rebalance and updates the UI with the results using a helper function
-    pub fn lazy_rebalance(&mut self) {
-        let balanced_portfolio = lazy_rebalance(self.contribution_amount, &mut self.portfolio);
-        //clear out the old results
-        self.table_results.items = vec![];
-        self.table_results.items = to_vec_display(balanced_portfolio);
+        let plan = if self.accounts.len() > 1 {
+            let account_values = self.account_values(self.active_account()?);
+
+            match self.rebalance_mode {
+                RebalanceMode::Lazy => lazy_account_rebalance(
+                    &self.contribution_amount,
+                    self.minimum_trade.as_ref(),
+                    &account_values,
+                    &self.portfolio,
+                ),
+                RebalanceMode::Full => full_account_rebalance(
+                    &self.contribution_amount,
+                    &account_values,
+                    &self.portfolio,
+                ),
+                RebalanceMode::Band => {
+                    return Err("Band rebalancing works on the whole household only".to_string())
+                }
+            }
+        } else {
+            match self.rebalance_mode {
+                RebalanceMode::Lazy => lazy_rebalance_with_minimum(
+                    &self.contribution_amount,
+                    self.minimum_trade.as_ref(),
+                    &self.portfolio,
+                ),
+                RebalanceMode::Full => full_rebalance(&self.contribution_amount, &self.portfolio),
+                RebalanceMode::Band => {
+                    band_rebalance(&self.contribution_amount, self.band_target, &self.portfolio)
+                }
+            }
+        };
+        self.results_title = format!("Rebalance ({})", self.rebalance_mode);
+        self.show_results(plan);
+
+        Ok(())
     }
-}
 
-pub struct Percent(pub f64);
+    /// Plans the sells that raise `amount` in cash, from the selected account when there are
+    /// several, and shows them in the Rebalance pane
+    pub fn withdraw(&mut self, amount: &BigRational) -> Result<(), String> {
+        self.check_loaded()?;
+        self.check_targets()?;
 
-pub fn create_target_map(path_to_targets: &str) -> HashMap<String, Percent> {
-    let mut reader = csv::ReaderBuilder::new()
-        .has_headers(false)
-        .from_path(path_to_targets)
-        .unwrap();
+        let available_values = if self.accounts.len() > 1 {
+            self.account_values(self.active_account()?)
+        } else {
+            self.portfolio
+                .iter()
+                .map(|asset| (asset.name.clone(), asset.value.clone()))
+                .collect()
+        };
 
-    let mut target_map = HashMap::new();
+        let plan = withdraw(amount, &available_values, &self.portfolio)?;
 
-    for result in reader.records() {
-        let record = result.unwrap();
+        self.results_title = format!("Withdraw ${}", format_rational(amount, 2));
+        self.show_results(plan);
 
-        let asset_name = record.get(0).unwrap().trim().to_string();
-        let allocation: Percent = {
-            let column = record.get(1).unwrap().trim();
+        Ok(())
+    }
 
-            let allocation = column.parse::<f64>().unwrap();
+    /// Executes the previewed trades: every delta is added to the asset's value in the
+    /// household and in the active account, and the accounts are saved
+    pub fn execute_trades(&mut self) -> Result<(), String> {
+        let account = self.active_account()?;
+        let plan = self.plan.take().ok_or("There are no trades to execute")?;
+        self.push_undo();
 
-            if allocation <= 0.0 {
+        let entry = JournalEntry::new(
+            self.results_title.clone(),
+            self.selected_account
+                .map(|index| self.accounts[index].name.clone()),
+            &self.portfolio,
+            &plan,
+        );
+        if let Some(snapshot) = self.undo_stack.last_mut() {
+            snapshot.executed = Some(ExecutedTrades {
+                action: entry.action.clone(),
+                account: entry.account.clone(),
+                plan: plan.clone(),
+            });
+        }
+
+        for (asset, trade) in self.portfolio.iter_mut().zip(plan.assets) {
+            let delta = trade.delta;
+            if delta.is_zero() {
                 continue;
             }
 
-            Percent(allocation)
-        };
+            let holdings = &mut self.accounts[account].holdings;
+            match holdings
+                .iter_mut()
+                .find(|holding| holding.name == asset.name)
+            {
+                Some(holding) => holding.set_value(&holding.value + &delta),
+                None => {
+                    // the trade buys an asset the account didn't hold yet
+                    let mut holding = create_asset(
+                        asset.name.clone(),
+                        &self.target_map[&asset.name],
+                        BigRational::zero(),
+                    );
+                    holding.price = asset.price.clone();
+                    holding.set_value(delta.clone());
+                    holdings.push(holding);
+                }
+            }
+            asset.set_value(&asset.value + delta);
+        }
+
+        self.results_title = format!("{} (executed)", self.results_title);
+
+        self.refresh_portfolio_table();
+        self.refresh_targets_table();
+
+        self.save_portfolio()
+            .map_err(|error| format!("Error saving portfolio: {}", error))?;
 
-        target_map.insert(asset_name, allocation);
+        self.record(entry)
     }
 
-    target_map
-}
+    /// Appends an entry to the journal file and the History pane
+    fn record(&mut self, entry: JournalEntry) -> Result<(), String> {
+        append_entry(&self.path_to_journal, &entry)
+            .map_err(|error| format!("Error saving journal: {}", error))?;
+        self.journal.push(entry);
+        self.refresh_history_table();
 
-pub fn create_portfolio(
-    path_to_portfolio: &str,
-    portfolio_value_index: usize,
-    target_map: &HashMap<String, Percent>,
-) -> Vec<Asset> {
-    let mut reader = csv::ReaderBuilder::new()
-        .has_headers(false)
-        .from_path(path_to_portfolio)
-        .unwrap();
-
-    let mut portfolio_map: HashMap<String, Asset> = HashMap::new();
-
-    for result in reader.records() {
-        let record = result.unwrap();
-
-        let asset_name = record.get(0).unwrap().trim().to_string();
-
-        let value = {
-            let value: String = record
-                .get(portfolio_value_index)
-                .unwrap()
-                .trim()
-                .chars()
-                .skip(1)
-                .collect();
-
-            value.parse::<f64>().unwrap()
-        };
+        Ok(())
+    }
+
+    /// Remembers the current portfolio and targets before a change. A new change can't be
+    /// redone after, so the redo history is dropped
+    fn push_undo(&mut self) {
+        let snapshot = self.snapshot();
+        self.undo_stack.push(snapshot);
+        self.redo_stack.clear();
+    }
+
+    /// A previewed plan isn't part of the state, undo never brings it back
+    fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            portfolio: self.portfolio.clone(),
+            holdings: self
+                .accounts
+                .iter()
+                .map(|account| account.holdings.clone())
+                .collect(),
+            target_tree: self.target_tree.clone(),
+            executed: None,
+        }
+    }
+
+    /// Goes back to the state before the last change and saves it. Undoing executed trades
+    /// journals the trades that take them back, the journal only ever grows
+    pub fn undo(&mut self) -> Result<(), String> {
+        let snapshot = self.undo_stack.pop().ok_or("Nothing to undo")?;
+        let mut current = self.snapshot();
+        current.executed = snapshot.executed.clone();
+        self.redo_stack.push(current);
 
-        match target_map.get(&asset_name) {
-            None => {}
-            Some(&Percent(target_allocation_percent)) => {
-                let target_allocation_percent =
-                    adjust_target_allocation_percent(target_allocation_percent);
+        let entry = snapshot.executed.as_ref().map(|executed| {
+            JournalEntry::new(
+                format!("Undo {}", executed.action),
+                executed.account.clone(),
+                &self.portfolio,
+                &reverse_plan(&executed.plan, &self.portfolio),
+            )
+        });
 
-                let asset = Asset::new(asset_name.clone(), target_allocation_percent, value);
+        self.restore(snapshot, entry)
+    }
+
+    /// Reapplies the last undone change and saves it. Redoing executed trades journals them
+    /// again
+    pub fn redo(&mut self) -> Result<(), String> {
+        let snapshot = self.redo_stack.pop().ok_or("Nothing to redo")?;
+        let mut current = self.snapshot();
+        current.executed = snapshot.executed.clone();
+        self.undo_stack.push(current);
+
+        let entry = snapshot.executed.as_ref().map(|executed| {
+            JournalEntry::new(
+                executed.action.clone(),
+                executed.account.clone(),
+                &self.portfolio,
+                &executed.plan,
+            )
+        });
+
+        self.restore(snapshot, entry)
+    }
 
-                portfolio_map.insert(asset_name, asset);
+    /// Puts a snapshot back and saves it. Trades are saved and journaled like executed ones,
+    /// whether or not the targets add up, other changes wait for the targets to add up
+    fn restore(&mut self, snapshot: Snapshot, trades: Option<JournalEntry>) -> Result<(), String> {
+        self.portfolio = snapshot.portfolio;
+        for (account, holdings) in self.accounts.iter_mut().zip(snapshot.holdings) {
+            account.holdings = holdings;
+        }
+        self.target_tree = snapshot.target_tree;
+        self.apply_targets();
+
+        self.refresh_portfolio_table();
+        self.refresh_targets_table();
+        self.table_targets.state.select(None);
+
+        match trades {
+            Some(entry) => {
+                self.save_portfolio()
+                    .map_err(|error| format!("Error saving portfolio: {}", error))?;
+                self.record(entry)
             }
+            None => self.save_if_balanced(),
         }
     }
 
-    for asset_name in target_map.keys() {
-        if portfolio_map.contains_key(asset_name) {
-            continue;
+    /// Throws away the previewed trades, leaving the portfolio as it was
+    pub fn discard_trades(&mut self) {
+        self.plan = None;
+        self.table_results.items = vec![];
+        self.table_results.state.select(None);
+        self.results_title = "Rebalance".to_string();
+        self.unspent_cash = None;
+    }
+
+    /// Value of each asset held in an account
+    fn account_values(&self, account: usize) -> HashMap<String, BigRational> {
+        self.accounts[account]
+            .holdings
+            .iter()
+            .map(|asset| (asset.name.clone(), asset.value.clone()))
+            .collect()
+    }
+
+    /// Plans the purchases of every contribution of `schedule`, the first one today, and shows
+    /// them in the Schedule pane. Nothing is traded or saved
+    pub fn plan_schedule(&mut self, schedule: Schedule) -> Result<(), String> {
+        self.check_loaded()?;
+        self.check_targets()?;
+        if schedule.periods() == 0 {
+            return Err(format!("{} has no contributions", schedule));
         }
 
-        let &Percent(target_allocation_percent) = target_map.get(asset_name).unwrap();
+        let periods = plan_schedule(
+            &self.portfolio,
+            &schedule,
+            &self.expected_returns,
+            Local::now().date_naive(),
+        );
+
+        // the header row is drawn by the pane
+        self.table_schedule.items = to_schedule_rows(&periods).split_off(1);
+        self.table_schedule.state.select(None);
+        self.schedule_summary = schedule_summary(&schedule, &periods);
+        self.schedule = Some(schedule);
+        self.schedule_periods = periods;
+        self.focus = Focus::Schedule;
+
+        Ok(())
+    }
+
+    /// Every contribution of the planned schedule
+    pub fn schedule_periods(&self) -> &[SchedulePeriod] {
+        &self.schedule_periods
+    }
+
+    /// Writes the purchases of the planned schedule to a csv file next to the portfolio
+    pub fn export_schedule(&mut self) -> Result<(), String> {
+        let csv = schedule_to_csv(&self.schedule_periods)
+            .map_err(|error| format!("Error exporting schedule: {}", error))?;
+        std::fs::write(&self.path_to_schedule, csv)
+            .map_err(|error| format!("Error exporting schedule: {}", error))?;
+
+        self.schedule_summary
+            .push(format!("Exported to {}", self.path_to_schedule));
+
+        Ok(())
+    }
+
+    /// Replays `backtest` over the price history with every strategy and shows the results in
+    /// the Backtest pane. Nothing is traded or saved
+    pub fn run_backtest(&mut self, backtest: Backtest) -> Result<(), String> {
+        self.check_loaded()?;
+        self.check_targets()?;
+        if self.prices.is_empty() {
+            return Err("Start the app with --prices prices.csv to run a backtest".to_string());
+        }
 
-        let target_allocation_percent = adjust_target_allocation_percent(target_allocation_percent);
+        let results = run_backtest(&self.portfolio, &self.prices, &backtest, self.band_target)?;
 
-        let asset = Asset::new(asset_name.clone(), target_allocation_percent, 0.0);
+        // the header row is drawn by the pane
+        self.table_backtest.items = to_backtest_rows(&results).split_off(1);
+        self.table_backtest.state.select(None);
+        self.backtest = Some(backtest);
+        self.backtest_results = results;
+        self.focus = Focus::Backtest;
 
-        portfolio_map.insert(asset_name.to_string(), asset);
+        Ok(())
     }
 
-    let mut portfolio = vec![];
+    /// Every strategy of the last backtest
+    pub fn backtest_results(&self) -> &[BacktestResult] {
+        &self.backtest_results
+    }
 
-    for (_asset_name, asset) in portfolio_map {
-        portfolio.push(asset);
+    /// Title of the History pane, warning about journal lines that were skipped
+    pub fn history_title(&self) -> String {
+        match self.journal_warnings.len() {
+            0 => "History (Enter marks an entry to compare)".to_string(),
+            1 => format!("History: skipped {}", self.journal_warnings[0]),
+            skipped => format!(
+                "History: skipped {} unreadable journal lines, the first is {}",
+                skipped, self.journal_warnings[0]
+            ),
+        }
     }
 
-    portfolio
+    /// What the last backtest replayed and over which dates
+    pub fn backtest_title(&self) -> String {
+        match self.backtest {
+            Some(ref backtest) => backtest_summary(backtest, &self.backtest_results),
+            None => String::new(),
+        }
+    }
+
+    /// The value of the portfolio under each strategy of the last backtest, days since the
+    /// first price date against dollars
+    pub fn backtest_lines(&self) -> Vec<(String, Vec<(f64, f64)>)> {
+        self.backtest_results
+            .iter()
+            .map(|result| {
+                let start = result.values.first().map(|(date, _)| *date);
+                let points = result
+                    .values
+                    .iter()
+                    .map(|(date, value)| {
+                        let days = start.map_or(0, |start| (*date - start).num_days());
+                        (days as f64, *value)
+                    })
+                    .collect();
+                (result.strategy.to_string(), points)
+            })
+            .collect()
+    }
+
+    /// Simulates the future value of the portfolio under `projection`, starting today, and
+    /// shows the percentiles in the Projection pane. Contributions are rebalanced with the
+    /// selected mode. Nothing is traded or saved
+    pub fn project(&mut self, projection: Projection) -> Result<(), String> {
+        self.check_loaded()?;
+        self.check_targets()?;
+        if self.assumptions.is_empty() {
+            return Err(
+                "Start the app with --assumptions assumptions.csv to run a projection".to_string(),
+            );
+        }
+
+        let result = simulate(
+            &self.portfolio,
+            &self.assumptions,
+            &projection,
+            self.simulation,
+            self.rebalance_mode,
+            self.band_target,
+            Local::now().date_naive(),
+        )?;
+
+        self.projection_summary = projection_summary(&projection, &result);
+        self.projection = Some(projection);
+        self.projection_result = Some(result);
+        self.focus = Focus::Projection;
+
+        Ok(())
+    }
+
+    /// The percentiles of the last projection
+    pub fn projection_result(&self) -> Option<&ProjectionResult> {
+        self.projection_result.as_ref()
+    }
+
+    /// The 10th, 50th and 90th percentile of the last projection followed by the goal, years
+    /// from today against dollars
+    pub fn projection_lines(&self) -> Vec<(String, Vec<(f64, f64)>)> {
+        let (projection, result) = match (&self.projection, &self.projection_result) {
+            (Some(projection), Some(result)) => (projection, result),
+            _ => return vec![],
+        };
+        let per_year = f64::from(projection.frequency.per_year());
+
+        let mut lines: Vec<(String, Vec<(f64, f64)>)> = ["10th", "median", "90th"]
+            .iter()
+            .enumerate()
+            .map(|(band, name)| {
+                let points = result
+                    .percentiles
+                    .iter()
+                    .enumerate()
+                    .map(|(period, percentiles)| (period as f64 / per_year, percentiles[band]))
+                    .collect();
+                (name.to_string(), points)
+            })
+            .collect();
+
+        let goal = to_f64(&projection.goal);
+        lines.push((
+            "goal".to_string(),
+            vec![(0.0, goal), (f64::from(projection.years), goal)],
+        ));
+
+        lines
+    }
+
+    /// Whether the last rebalance sells the named asset
+    pub fn is_sell(&self, asset_name: &str) -> bool {
+        self.planned_trade(asset_name)
+            .is_some_and(|trade| trade.delta < BigRational::zero())
+    }
+
+    /// The trade the last rebalance plans for the named asset
+    fn planned_trade(&self, asset_name: &str) -> Option<&AssetPlan> {
+        self.plan
+            .as_ref()?
+            .assets
+            .iter()
+            .find(|trade| trade.name == asset_name)
+    }
+
+    /// Rounds the trades of the last rebalance and fills the Rebalance pane with them
+    fn show_results(&mut self, mut plan: RebalancePlan) {
+        let unspent_cash = round_to_whole_shares(&self.portfolio, &mut plan);
+
+        //clear out the old results
+        self.table_results.items = vec![];
+        self.table_results.items = to_vec_display(&self.portfolio, &plan);
+        self.plan = Some(plan);
+
+        // rounding to whole shares or dropping trades can leave some cash behind, report it
+        // rather than hide it
+        self.unspent_cash = if !unspent_cash.is_zero()
+            || self.portfolio.iter().any(|asset| asset.price.is_some())
+        {
+            Some(unspent_cash)
+        } else {
+            None
+        };
+
+        if let Some(unspent_cash) = &self.unspent_cash {
+            self.table_results.items.push(vec![
+                "Unspent cash".to_string(),
+                String::new(),
+                String::new(),
+                String::new(),
+                format_rational(unspent_cash, 2),
+                String::new(),
+            ]);
+        }
+    }
 }
 
-pub fn adjust_target_allocation_percent(target_allocation_percent: f64) -> f64 {
-    target_allocation_percent / 100.0
+/// Parses a target percentage typed into the TUI. A 0% target is refused since the targets
+/// loader skips such rows and the rebalance has no target value to measure the asset against
+fn parse_percent(input: &str) -> Result<BigRational, String> {
+    parse_decimal(input)
+        .filter(|percent| {
+            *percent > BigRational::zero() && *percent <= BigRational::from_integer(100.into())
+        })
+        .ok_or_else(|| format!("{} is not a percentage above 0 and up to 100", input))
 }
@@ -1,5 +1,4 @@
 //#[cfg(feature = "termion")]
 pub mod app;
 pub mod event;
-pub mod rebalance;
 pub mod ui;
@@ -1,14 +1,18 @@
 use tui::{
     backend::Backend,
-    layout::{Constraint, Direction, Layout},
+    layout::{Constraint, Direction, Layout, Rect},
     style::{Color, Modifier, Style},
+    symbols,
     text::{Span, Spans, Text},
-    widgets::{Block, Borders, Cell, Paragraph, Row, Table},
+    widgets::{
+        Axis, BarChart, Block, Borders, Cell, Chart, Dataset, GraphType, Paragraph, Row, Table,
+        Wrap,
+    },
     Frame,
 };
 
 use super::app::App;
-use super::app::InputMode;
+use super::app::{Focus, InputMode};
 
 //uses examples from https://github.com/fdehau/tui-rs/tree/master/examples
 
@@ -32,8 +36,9 @@ pub fn draw<B: Backend>(f: &mut Frame<B>, app: &mut App) {
         .margin(0)
         .constraints(
             [
-                Constraint::Length(1),
                 Constraint::Length(3),
+                Constraint::Length(3),
+                Constraint::Length(6),
                 Constraint::Min(1),
             ]
             .as_ref(),
@@ -50,7 +55,31 @@ pub fn draw<B: Backend>(f: &mut Frame<B>, app: &mut App) {
                 Span::styled("e", Style::default().add_modifier(Modifier::BOLD)),
                 Span::raw(" to start editing, "),
                 Span::styled("r", Style::default().add_modifier(Modifier::BOLD)),
-                Span::raw(" to rebalance."),
+                Span::raw(" to rebalance, "),
+                Span::styled("w", Style::default().add_modifier(Modifier::BOLD)),
+                Span::raw(" to withdraw, "),
+                Span::styled("s", Style::default().add_modifier(Modifier::BOLD)),
+                Span::raw(" to plan a contribution schedule, "),
+                Span::styled("b", Style::default().add_modifier(Modifier::BOLD)),
+                Span::raw(" to backtest, "),
+                Span::styled("p", Style::default().add_modifier(Modifier::BOLD)),
+                Span::raw(" to project, "),
+                Span::styled("a", Style::default().add_modifier(Modifier::BOLD)),
+                Span::raw(" to switch account, "),
+                Span::styled("i", Style::default().add_modifier(Modifier::BOLD)),
+                Span::raw("/"),
+                Span::styled("n", Style::default().add_modifier(Modifier::BOLD)),
+                Span::raw("/"),
+                Span::styled("d", Style::default().add_modifier(Modifier::BOLD)),
+                Span::raw(" to insert/rename/delete an asset, "),
+                Span::styled("u", Style::default().add_modifier(Modifier::BOLD)),
+                Span::raw("/"),
+                Span::styled("Ctrl-R", Style::default().add_modifier(Modifier::BOLD)),
+                Span::raw(" to undo/redo, "),
+                Span::styled("c", Style::default().add_modifier(Modifier::BOLD)),
+                Span::raw(" to toggle the chart, "),
+                Span::styled("Tab", Style::default().add_modifier(Modifier::BOLD)),
+                Span::raw(" to switch table."),
             ],
             Style::default().add_modifier(Modifier::RAPID_BLINK),
         ),
@@ -60,7 +89,10 @@ pub fn draw<B: Backend>(f: &mut Frame<B>, app: &mut App) {
                 Span::styled("Esc", Style::default().add_modifier(Modifier::BOLD)),
                 Span::raw(" to stop editing, "),
                 Span::styled("Enter", Style::default().add_modifier(Modifier::BOLD)),
-                Span::raw(" to new amount"),
+                Span::raw(match app.focus {
+                    Focus::Targets => " to set the target %",
+                    _ => " to new amount",
+                }),
             ],
             Style::default(),
         ),
@@ -70,22 +102,110 @@ pub fn draw<B: Backend>(f: &mut Frame<B>, app: &mut App) {
                 Span::styled("Esc", Style::default().add_modifier(Modifier::BOLD)),
                 Span::raw(" to return, "),
                 Span::styled("Enter", Style::default().add_modifier(Modifier::BOLD)),
-                Span::raw(" rebalance amount"),
+                Span::raw(" rebalance amount, "),
+                Span::styled("Tab", Style::default().add_modifier(Modifier::BOLD)),
+                Span::raw(format!(" to change mode ({})", app.rebalance_mode)),
+            ],
+            Style::default(),
+        ),
+        InputMode::Withdraw => (
+            vec![
+                Span::raw("Press "),
+                Span::styled("Esc", Style::default().add_modifier(Modifier::BOLD)),
+                Span::raw(" to return, "),
+                Span::styled("Enter", Style::default().add_modifier(Modifier::BOLD)),
+                Span::raw(" cash amount to raise"),
+            ],
+            Style::default(),
+        ),
+        InputMode::Schedule => (
+            vec![
+                Span::raw("Press "),
+                Span::styled("Esc", Style::default().add_modifier(Modifier::BOLD)),
+                Span::raw(" to return, "),
+                Span::styled("Enter", Style::default().add_modifier(Modifier::BOLD)),
+                Span::raw(" amount, frequency (weekly, biweekly or monthly) and months"),
+            ],
+            Style::default(),
+        ),
+        InputMode::Backtest => (
+            vec![
+                Span::raw("Press "),
+                Span::styled("Esc", Style::default().add_modifier(Modifier::BOLD)),
+                Span::raw(" to return, "),
+                Span::styled("Enter", Style::default().add_modifier(Modifier::BOLD)),
+                Span::raw(" amount, frequency and months between calendar rebalances"),
+            ],
+            Style::default(),
+        ),
+        InputMode::Projection => (
+            vec![
+                Span::raw("Press "),
+                Span::styled("Esc", Style::default().add_modifier(Modifier::BOLD)),
+                Span::raw(" to return, "),
+                Span::styled("Enter", Style::default().add_modifier(Modifier::BOLD)),
+                Span::raw(" amount, frequency, years and goal"),
+            ],
+            Style::default(),
+        ),
+        InputMode::Preview => (
+            vec![
+                Span::raw("Review the trades, press "),
+                Span::styled("y", Style::default().add_modifier(Modifier::BOLD)),
+                Span::raw(" to execute them and save the portfolio, "),
+                Span::styled("Esc", Style::default().add_modifier(Modifier::BOLD)),
+                Span::raw(" to discard them"),
+            ],
+            Style::default().fg(Color::LightGreen),
+        ),
+        InputMode::Insert => (
+            vec![
+                Span::raw("Press "),
+                Span::styled("Esc", Style::default().add_modifier(Modifier::BOLD)),
+                Span::raw(" to return, "),
+                Span::styled("Enter", Style::default().add_modifier(Modifier::BOLD)),
+                Span::raw(" name, target %, value of the new asset"),
+            ],
+            Style::default(),
+        ),
+        InputMode::Rename => (
+            vec![
+                Span::raw("Press "),
+                Span::styled("Esc", Style::default().add_modifier(Modifier::BOLD)),
+                Span::raw(" to return, "),
+                Span::styled("Enter", Style::default().add_modifier(Modifier::BOLD)),
+                Span::raw(" new name of the asset"),
             ],
             Style::default(),
         ),
+        InputMode::Delete => (
+            vec![
+                Span::raw(format!(
+                    "Delete {} from the targets and every account? Press ",
+                    app.selected_asset().unwrap_or_default()
+                )),
+                Span::styled("y", Style::default().add_modifier(Modifier::BOLD)),
+                Span::raw(" to confirm, any other key to keep it"),
+            ],
+            Style::default().fg(Color::LightRed),
+        ),
         InputMode::ErrorDisplay => (
-            vec![Span::styled(
-                "Input should be in the form of a dollar amount",
-                Style::default().add_modifier(Modifier::BOLD),
-            )],
+            vec![
+                Span::styled(
+                    app.error_msg.clone(),
+                    Style::default().add_modifier(Modifier::BOLD),
+                ),
+                Span::raw(" (press "),
+                Span::styled("Esc", Style::default().add_modifier(Modifier::BOLD)),
+                Span::raw(")"),
+            ],
             Style::default(),
         ),
     };
 
     let mut text = Text::from(Spans::from(msg));
     text.patch_style(style);
-    let help_message = Paragraph::new(text);
+    let help_message = Paragraph::new(text).wrap(Wrap { trim: true });
     f.render_widget(help_message, input_chunks[0]);
 
     let input = Paragraph::new(app.input.as_ref())
@@ -93,14 +213,46 @@ pub fn draw<B: Backend>(f: &mut Frame<B>, app: &mut App) {
             InputMode::Normal => Style::default(),
             InputMode::Editing => Style::default().fg(Color::Yellow),
             InputMode::Exec => Style::default().fg(Color::LightRed),
+            InputMode::Withdraw => Style::default().fg(Color::Magenta),
+            InputMode::Insert | InputMode::Rename => Style::default().fg(Color::Yellow),
+            InputMode::Schedule | InputMode::Backtest | InputMode::Projection => {
+                Style::default().fg(Color::Cyan)
+            }
+            InputMode::Delete | InputMode::Preview => Style::default(),
             InputMode::ErrorDisplay => Style::default().fg(Color::White).bg(Color::Red),
         })
-        .block(Block::default().borders(Borders::ALL).title("Input"));
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title(match app.input_mode {
+                    InputMode::Exec => format!("Input ({} rebalance)", app.rebalance_mode),
+                    InputMode::Withdraw => "Input (withdrawal)".to_string(),
+                    InputMode::Insert => "Input (name, target %, value)".to_string(),
+                    InputMode::Rename => "Input (new name)".to_string(),
+                    InputMode::Schedule => "Input (amount, frequency, months)".to_string(),
+                    InputMode::Backtest => "Input (amount, frequency, calendar months)".to_string(),
+                    InputMode::Projection => "Input (amount, frequency, years, goal)".to_string(),
+                    InputMode::Editing if app.focus == Focus::Targets => {
+                        "Input (target % of the parent row)".to_string()
+                    }
+                    _ => "Input".to_string(),
+                }),
+        );
     f.render_widget(input, input_chunks[1]);
 
+    let summary: Vec<Spans> = app
+        .drift_summary()
+        .into_iter()
+        .map(|line| Spans::from(Span::raw(line)))
+        .collect();
+    let summary = Paragraph::new(summary)
+        .block(Block::default().borders(Borders::ALL).title("Drift"))
+        .wrap(Wrap { trim: true });
+    f.render_widget(summary, input_chunks[2]);
+
     let selected_style = Style::default().add_modifier(Modifier::REVERSED);
     let normal_style = Style::default().bg(Color::Green);
-    let header_cells = ["Ticker Symbol", "Allocation"]
+    let header_cells = ["Asset Class", "Target", "Actual", "Drift"]
         .iter()
         .map(|h| Cell::from(*h).style(Style::default().fg(Color::White)));
 
@@ -120,17 +272,36 @@ pub fn draw<B: Backend>(f: &mut Frame<B>, app: &mut App) {
         Row::new(cells).height(height as u16).bottom_margin(1)
     });
 
+    // the sum check counts the percentage being typed in so mistakes show up straight away
+    let targets_title = match app.targets_problem() {
+        Some(problem) => Span::styled(
+            format!("Targets ({})", problem),
+            Style::default().fg(Color::LightRed),
+        ),
+        None => Span::raw("Targets"),
+    };
+
     let targets = Table::new(rows)
         .header(header)
-        .block(Block::default().borders(Borders::ALL).title("Targets"))
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .border_style(focus_style(app, Focus::Targets))
+                .title(targets_title),
+        )
         .highlight_style(selected_style)
         .highlight_symbol(">> ")
         .widths(&[
-            Constraint::Percentage(50),
-            Constraint::Length(30),
-            Constraint::Max(10),
+            Constraint::Percentage(40),
+            Constraint::Percentage(20),
+            Constraint::Percentage(20),
+            Constraint::Percentage(20),
         ]);
-    f.render_stateful_widget(targets, rects[0], &mut app.table_targets.state);
+    if app.show_chart {
+        draw_allocation_chart(f, app, rects[0]);
+    } else {
+        f.render_stateful_widget(targets, rects[0], &mut app.table_targets.state);
+    }
 
     let selected_style = Style::default().add_modifier(Modifier::REVERSED);
     let normal_style = Style::default().bg(Color::Green);
@@ -140,6 +311,7 @@ pub fn draw<B: Backend>(f: &mut Frame<B>, app: &mut App) {
         "New Holdings %",
         "Target Value",
         "$ to buy/sell",
+        "Shares",
     ]
     .iter()
     .map(|h| Cell::from(*h).style(Style::default().fg(Color::White)));
@@ -157,25 +329,48 @@ pub fn draw<B: Backend>(f: &mut Frame<B>, app: &mut App) {
             .unwrap_or(0)
             + 1;
         let cells = item.iter().map(|c| Cell::from(c.as_ref()));
-        Row::new(cells).height(height as u16).bottom_margin(1)
+        Row::new(cells)
+            .height(height as u16)
+            .bottom_margin(1)
+            .style(result_style(app, &item[0]))
     });
 
     // Render the results of the rebalance in a small table with fields that aren't already shown in th UI
     let results = Table::new(rows)
         .header(header)
-        .block(Block::default().borders(Borders::ALL).title("Rebalance"))
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title(match app.input_mode {
+                    InputMode::Preview => format!("{} preview", app.results_title),
+                    _ => app.results_title.clone(),
+                }),
+        )
         .highlight_style(selected_style)
         .highlight_symbol(">> ")
         .widths(&[
             Constraint::Percentage(20),
-            Constraint::Percentage(20),
-            Constraint::Percentage(20),
-            Constraint::Percentage(20),
-            Constraint::Percentage(20),
+            Constraint::Percentage(16),
+            Constraint::Percentage(16),
+            Constraint::Percentage(16),
+            Constraint::Percentage(16),
+            Constraint::Percentage(16),
             //Constraint::Length(20),
             //Constraint::Max(15),
         ]);
-    f.render_stateful_widget(results, rects[1], &mut app.table_results.state);
+    if !app.load_errors.is_empty() {
+        f.render_widget(load_errors(app), rects[1]);
+    } else if app.focus == Focus::History {
+        draw_history(f, app, rects[1]);
+    } else if app.focus == Focus::Schedule {
+        draw_schedule(f, app, rects[1]);
+    } else if app.focus == Focus::Backtest {
+        draw_backtest(f, app, rects[1]);
+    } else if app.focus == Focus::Projection {
+        draw_projection(f, app, rects[1]);
+    } else {
+        f.render_stateful_widget(results, rects[1], &mut app.table_results.state);
+    }
 
     let selected_style = Style::default().add_modifier(Modifier::REVERSED);
     let normal_style = Style::default().bg(Color::Green);
@@ -196,12 +391,20 @@ pub fn draw<B: Backend>(f: &mut Frame<B>, app: &mut App) {
             .unwrap_or(0)
             + 1;
         let cells = item.iter().map(|c| Cell::from(c.as_ref()));
-        Row::new(cells).height(height as u16).bottom_margin(1)
+        Row::new(cells)
+            .height(height as u16)
+            .bottom_margin(1)
+            .style(band_style(app, &item[0]))
     });
 
     let t = Table::new(rows)
         .header(header)
-        .block(Block::default().borders(Borders::ALL).title("Portfolio"))
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .border_style(focus_style(app, Focus::Portfolio))
+                .title(app.portfolio_title()),
+        )
         .highlight_style(selected_style)
         .highlight_symbol(">> ")
         .widths(&[
@@ -209,5 +412,398 @@ pub fn draw<B: Backend>(f: &mut Frame<B>, app: &mut App) {
             Constraint::Length(30),
             Constraint::Max(10),
         ]);
-    f.render_stateful_widget(t, input_chunks[2], &mut app.table_portfolio.state);
+    f.render_stateful_widget(t, input_chunks[3], &mut app.table_portfolio.state);
+}
+
+/// Grouped bars of the current (C), target (T) and post-rebalance (N) allocation of every
+/// asset, out of band assets are drawn in red
+fn draw_allocation_chart<B: Backend>(f: &mut Frame<B>, app: &App, area: Rect) {
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .title("Allocation % (C current, T target, N after the rebalance)");
+    let inner = block.inner(area);
+    f.render_widget(block, area);
+
+    let bars = app.allocation_bars();
+    if bars.is_empty() {
+        return;
+    }
+    let max = bars
+        .iter()
+        .flat_map(|(_, percents, _)| percents.iter().copied())
+        .max()
+        .unwrap_or(0)
+        .max(1);
+
+    let groups = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints(
+            bars.iter()
+                .map(|_| Constraint::Ratio(1, bars.len() as u32))
+                .collect::<Vec<_>>(),
+        )
+        .split(inner);
+
+    for ((name, percents, out_of_band), group) in bars.iter().zip(groups) {
+        let rows = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Min(1), Constraint::Length(1)].as_ref())
+            .split(group);
+
+        let style = if *out_of_band {
+            Style::default().fg(Color::LightRed)
+        } else {
+            Style::default().fg(Color::Green)
+        };
+        let data = [("C", percents[0]), ("T", percents[1]), ("N", percents[2])];
+        let chart = BarChart::default()
+            .data(&data)
+            .max(max)
+            .bar_width((rows[0].width.saturating_sub(2) / 3).clamp(1, 5))
+            .bar_gap(1)
+            .bar_style(style)
+            .value_style(style.add_modifier(Modifier::REVERSED));
+        f.render_widget(chart, rows[0]);
+
+        let label = Paragraph::new(Span::styled(name.as_str(), style)).wrap(Wrap { trim: true });
+        f.render_widget(label, rows[1]);
+    }
+}
+
+/// Lists the journal of executed rebalances above the details of the selected entry
+fn draw_history<B: Backend>(f: &mut Frame<B>, app: &mut App, area: Rect) {
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Percentage(40), Constraint::Percentage(60)].as_ref())
+        .split(area);
+
+    let header_style = Style::default().bg(Color::Green);
+    let header_cells = ["#", "Date", "Action", "Amount", "Account"]
+        .iter()
+        .map(|h| Cell::from(*h).style(Style::default().fg(Color::White)));
+    let header = Row::new(header_cells).style(header_style).height(1);
+
+    let rows = app
+        .table_history
+        .items
+        .iter()
+        .map(|item| Row::new(item.iter().map(|c| Cell::from(c.as_ref()))));
+
+    let history = Table::new(rows)
+        .header(header)
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .border_style(focus_style(app, Focus::History))
+                .title(app.history_title()),
+        )
+        .highlight_style(Style::default().add_modifier(Modifier::REVERSED))
+        .highlight_symbol(">> ")
+        .widths(&[
+            Constraint::Length(5),
+            Constraint::Length(17),
+            Constraint::Percentage(35),
+            Constraint::Percentage(20),
+            Constraint::Percentage(20),
+        ]);
+    f.render_stateful_widget(history, chunks[0], &mut app.table_history.state);
+
+    let (title, header, rows) = app.history_detail();
+    let widths: Vec<Constraint> = header
+        .iter()
+        .enumerate()
+        .map(|(index, _)| {
+            if index == 0 {
+                Constraint::Percentage(25)
+            } else {
+                Constraint::Percentage(75 / (header.len() as u16 - 1))
+            }
+        })
+        .collect();
+    let header = Row::new(
+        header
+            .into_iter()
+            .map(|h| Cell::from(h).style(Style::default().fg(Color::White))),
+    )
+    .style(header_style)
+    .height(1);
+    let rows = rows
+        .into_iter()
+        .map(|row| Row::new(row.into_iter().map(Cell::from)));
+
+    let detail = Table::new(rows)
+        .header(header)
+        .block(Block::default().borders(Borders::ALL).title(title))
+        .widths(&widths);
+    f.render_widget(detail, chunks[1]);
+}
+
+/// Lists the purchases of the planned contribution schedule above its totals
+fn draw_schedule<B: Backend>(f: &mut Frame<B>, app: &mut App, area: Rect) {
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(1), Constraint::Length(6)].as_ref())
+        .split(area);
+
+    let header_cells = [
+        "#",
+        "Date",
+        "Asset",
+        "$ to buy",
+        "Shares",
+        "Value after",
+        "New %",
+        "Target %",
+    ]
+    .iter()
+    .map(|h| Cell::from(*h).style(Style::default().fg(Color::White)));
+    let header = Row::new(header_cells)
+        .style(Style::default().bg(Color::Green))
+        .height(1);
+
+    let rows = app
+        .table_schedule
+        .items
+        .iter()
+        .map(|item| Row::new(item.iter().map(|c| Cell::from(c.as_ref()))));
+
+    let title = match app.schedule {
+        Some(ref schedule) => format!("Schedule: {} (x exports to csv)", schedule),
+        None => "Schedule".to_string(),
+    };
+    let schedule = Table::new(rows)
+        .header(header)
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .border_style(focus_style(app, Focus::Schedule))
+                .title(title),
+        )
+        .highlight_style(Style::default().add_modifier(Modifier::REVERSED))
+        .highlight_symbol(">> ")
+        .widths(&[
+            Constraint::Length(3),
+            Constraint::Length(10),
+            Constraint::Percentage(25),
+            Constraint::Length(9),
+            Constraint::Length(6),
+            Constraint::Length(11),
+            Constraint::Length(7),
+            Constraint::Length(8),
+        ]);
+    f.render_stateful_widget(schedule, chunks[0], &mut app.table_schedule.state);
+
+    let summary: Vec<Spans> = app
+        .schedule_summary
+        .iter()
+        .map(|line| Spans::from(Span::raw(line.as_str())))
+        .collect();
+    let summary = Paragraph::new(summary)
+        .block(Block::default().borders(Borders::ALL))
+        .wrap(Wrap { trim: true });
+    f.render_widget(summary, chunks[1]);
+}
+
+/// Plots the portfolio value under every strategy of the last backtest above a table of how
+/// each one did
+fn draw_backtest<B: Backend>(f: &mut Frame<B>, app: &mut App, area: Rect) {
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(1), Constraint::Length(6)].as_ref())
+        .split(area);
+
+    let lines = app.backtest_lines();
+    let colors = [Color::Green, Color::Yellow, Color::Magenta];
+    let datasets = lines
+        .iter()
+        .zip(colors.iter().cycle())
+        .map(|((name, points), color)| {
+            Dataset::default()
+                .name(name.as_str())
+                .marker(symbols::Marker::Braille)
+                .graph_type(GraphType::Line)
+                .style(Style::default().fg(*color))
+                .data(points)
+        })
+        .collect();
+
+    let points = || lines.iter().flat_map(|(_, points)| points.iter());
+    let days = points().map(|(day, _)| *day).fold(0.0, f64::max);
+    let low = points().map(|(_, value)| *value).fold(f64::MAX, f64::min);
+    let high = points().map(|(_, value)| *value).fold(0.0, f64::max);
+    let low = if low > high { 0.0 } else { low };
+
+    let values = app.backtest_results().first().map(|result| &result.values);
+    let (start, end) = match values {
+        Some(values) if !values.is_empty() => (
+            values[0].0.to_string(),
+            values[values.len() - 1].0.to_string(),
+        ),
+        _ => (String::new(), String::new()),
+    };
+
+    let chart = Chart::new(datasets)
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .border_style(focus_style(app, Focus::Backtest))
+                .title(format!("Backtest: {}", app.backtest_title())),
+        )
+        .x_axis(
+            Axis::default()
+                .bounds([0.0, days.max(1.0)])
+                .labels(vec![Span::raw(start), Span::raw(end)]),
+        )
+        .y_axis(
+            Axis::default()
+                .bounds([low, high.max(low + 1.0)])
+                .labels(vec![
+                    Span::raw(format!("${:.0}", low)),
+                    Span::raw(format!("${:.0}", high)),
+                ]),
+        );
+    f.render_widget(chart, chunks[0]);
+
+    let header_cells = [
+        "Strategy",
+        "Final value",
+        "Contributed",
+        "Sold",
+        "Turnover %",
+        "Trades",
+        "Max drift",
+        "CAGR %",
+    ]
+    .iter()
+    .map(|h| Cell::from(*h).style(Style::default().fg(Color::White)));
+    let header = Row::new(header_cells)
+        .style(Style::default().bg(Color::Green))
+        .height(1);
+
+    let rows = app
+        .table_backtest
+        .items
+        .iter()
+        .map(|item| Row::new(item.iter().map(|c| Cell::from(c.as_ref()))));
+
+    let results = Table::new(rows)
+        .header(header)
+        .block(Block::default().borders(Borders::ALL))
+        .highlight_style(Style::default().add_modifier(Modifier::REVERSED))
+        .highlight_symbol(">> ")
+        .widths(&[
+            Constraint::Length(8),
+            Constraint::Length(13),
+            Constraint::Length(13),
+            Constraint::Length(11),
+            Constraint::Length(10),
+            Constraint::Length(6),
+            Constraint::Length(9),
+            Constraint::Length(7),
+        ]);
+    f.render_stateful_widget(results, chunks[1], &mut app.table_backtest.state);
+}
+
+/// Plots the 10th, 50th and 90th percentile of the projected portfolio value against the goal,
+/// above the chance of reaching it
+fn draw_projection<B: Backend>(f: &mut Frame<B>, app: &App, area: Rect) {
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(1), Constraint::Length(5)].as_ref())
+        .split(area);
+
+    let lines = app.projection_lines();
+    let points = || lines.iter().flat_map(|(_, points)| points.iter());
+    let years = points().map(|(year, _)| *year).fold(0.0, f64::max);
+    let high = points().map(|(_, value)| *value).fold(0.0, f64::max);
+
+    let colors = [Color::Yellow, Color::Green, Color::Cyan, Color::LightRed];
+    let datasets = lines
+        .iter()
+        .zip(colors.iter())
+        .map(|((name, points), color)| {
+            Dataset::default()
+                .name(name.as_str())
+                .marker(symbols::Marker::Braille)
+                .graph_type(GraphType::Line)
+                .style(Style::default().fg(*color))
+                .data(points)
+        })
+        .collect();
+
+    let title = match app.projection {
+        Some(ref projection) => format!("Projection: {}", projection),
+        None => "Projection".to_string(),
+    };
+    let chart = Chart::new(datasets)
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .border_style(focus_style(app, Focus::Projection))
+                .title(title),
+        )
+        .x_axis(Axis::default().bounds([0.0, years.max(1.0)]).labels(vec![
+            Span::raw("now"),
+            Span::raw(format!("{:.0} years", years)),
+        ]))
+        .y_axis(
+            Axis::default()
+                .bounds([0.0, high.max(1.0)])
+                .labels(vec![Span::raw("$0"), Span::raw(format!("${:.0}", high))]),
+        );
+    f.render_widget(chart, chunks[0]);
+
+    let summary: Vec<Spans> = app
+        .projection_summary
+        .iter()
+        .map(|line| Spans::from(Span::raw(line.as_str())))
+        .collect();
+    let summary = Paragraph::new(summary)
+        .block(Block::default().borders(Borders::ALL))
+        .wrap(Wrap { trim: true });
+    f.render_widget(summary, chunks[1]);
+}
+
+/// Lists every problem found in the targets and portfolio files in place of the results
+fn load_errors<'a>(app: &App) -> Paragraph<'a> {
+    let lines: Vec<Spans> = app
+        .load_errors
+        .iter()
+        .map(|error| Spans::from(Span::raw(error.to_string())))
+        .collect();
+
+    Paragraph::new(lines)
+        .style(Style::default().fg(Color::LightRed))
+        .block(Block::default().borders(Borders::ALL).title("Errors"))
+        .wrap(Wrap { trim: false })
+}
+
+/// Highlights the border of the table the arrow keys act on
+fn focus_style(app: &App, focus: Focus) -> Style {
+    if app.focus == focus {
+        Style::default().fg(Color::Yellow)
+    } else {
+        Style::default()
+    }
+}
+
+/// Highlights the rows of assets that have drifted outside their tolerance band
+fn band_style(app: &App, asset_name: &str) -> Style {
+    if app.is_out_of_band(asset_name) {
+        Style::default().fg(Color::LightRed)
+    } else {
+        Style::default()
+    }
+}
+
+/// Greys out the rows whose trade was dropped for being under the minimum trade and
+/// colours the sells so a withdrawal's sell list stands out
+fn result_style(app: &App, asset_name: &str) -> Style {
+    if app.is_dropped_trade(asset_name) {
+        Style::default().fg(Color::DarkGray)
+    } else if app.is_sell(asset_name) {
+        Style::default().fg(Color::Magenta)
+    } else {
+        band_style(app, asset_name)
+    }
 }